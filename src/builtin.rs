@@ -1,103 +1,387 @@
+use crate::toml::{CompletionSpec, SubcommandSpec};
 use crossterm::queue;
 use crossterm::style::*;
 use std::io::{stdout, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::time::SystemTime;
 
-pub fn cp(from: &Path, to: &Path) {
+pub fn cp(from: &Path, to: &Path) -> i32 {
     match std::fs::copy(from, to) {
-        Ok(_) => {}
-        Err(e) => {
-            queue!(
-                stdout(),
-                SetForegroundColor(Color::Red),
-                Print("Error: "),
-                ResetColor,
-                Print(e)
-            )
-            .unwrap();
-            stdout().flush().unwrap()
-        }
+        Ok(_) => 0,
+        Err(e) => print_error(e),
     }
 }
 
-pub fn rm(p: &Path) {
+pub fn rm(p: &Path) -> i32 {
     match std::fs::remove_file(p) {
-        Ok(_) => {}
-        Err(e) => {
-            queue!(
-                stdout(),
-                SetForegroundColor(Color::Red),
-                Print("Error: "),
-                ResetColor,
-                Print(e)
-            )
-            .unwrap();
-            stdout().flush().unwrap()
-        }
+        Ok(_) => 0,
+        Err(e) => print_error(e),
     }
 }
 
-pub fn rmdir(p: &Path) {
+pub fn rmdir(p: &Path) -> i32 {
     match std::fs::remove_dir(p) {
-        Ok(_) => {}
-        Err(e) => {
+        Ok(_) => 0,
+        Err(e) => print_error(e),
+    }
+}
+
+pub fn touch(p: &Path) -> i32 {
+    let result = if p.exists() {
+        std::fs::File::open(p).and_then(|f| f.set_modified(SystemTime::now()))
+    } else {
+        std::fs::File::create(p).map(|_| ())
+    };
+    match result {
+        Ok(_) => 0,
+        Err(e) => print_error(e),
+    }
+}
+
+/// Print an error the way the builtins do, and give the exit status of a failed builtin
+pub fn print_error(e: impl std::fmt::Display) -> i32 {
+    queue!(
+        stdout(),
+        SetForegroundColor(Color::Red),
+        Print("Error: "),
+        ResetColor,
+        Print(e)
+    )
+    .unwrap();
+    stdout().flush().unwrap();
+    1
+}
+
+/// complete                         List commands with a completion spec
+/// complete CMD                     Show the spec for CMD
+/// complete CMD [SUB] [FLAGS] [@C]  Register SUB and FLAGS for CMD.
+///                                  Output lines of the command C become candidates.
+pub fn complete(args: &[String], flags: &[String]) -> i32 {
+    let (command, rest) = match args.split_first() {
+        Some(a) => a,
+        None => {
+            for spec in crate::completion::specs() {
+                queue!(stdout(), Print(spec.command), Print("\r\n")).unwrap();
+            }
+            stdout().flush().unwrap();
+            return 0;
+        }
+    };
+
+    if rest.is_empty() && flags.is_empty() {
+        match crate::completion::specs()
+            .into_iter()
+            .find(|s| &s.command == command)
+        {
+            Some(spec) => print_spec(&spec),
+            None => return print_error(format!("no completion spec for {}\r\n", command)),
+        }
+        stdout().flush().unwrap();
+        return 0;
+    }
+
+    let (dynamic, names): (Vec<&String>, Vec<&String>) =
+        rest.iter().partition(|a| a.starts_with('@'));
+    let dynamic = dynamic.first().map(|d| d[1..].to_string());
+
+    let mut spec = CompletionSpec {
+        command: command.clone(),
+        flags: vec![],
+        dynamic: None,
+        subcommands: vec![],
+    };
+    match names.first() {
+        Some(name) => spec.subcommands.push(SubcommandSpec {
+            name: name.to_string(),
+            flags: flags.to_vec(),
+            dynamic,
+        }),
+        None => {
+            spec.flags = flags.to_vec();
+            spec.dynamic = dynamic;
+        }
+    }
+    crate::completion::register(spec);
+    0
+}
+
+fn print_spec(spec: &CompletionSpec) {
+    queue!(
+        stdout(),
+        Print(&spec.command),
+        Print(" "),
+        Print(spec.flags.join(" ")),
+        Print("\r\n")
+    )
+    .unwrap();
+    if let Some(d) = &spec.dynamic {
+        queue!(stdout(), Print("  @"), Print(d), Print("\r\n")).unwrap();
+    }
+    for sub in &spec.subcommands {
+        queue!(
+            stdout(),
+            Print("  "),
+            Print(&sub.name),
+            Print(" "),
+            Print(sub.flags.join(" ")),
+            Print("\r\n")
+        )
+        .unwrap();
+        if let Some(d) = &sub.dynamic {
+            queue!(stdout(), Print("    @"), Print(d), Print("\r\n")).unwrap();
+        }
+    }
+}
+
+/// set -o           Show the line editing mode
+/// set -o vi        Edit the command line like vi
+/// set -o emacs     Edit the command line like emacs
+/// set +o OPTION    Turn OPTION off, so "set +o emacs" edits like vi
+pub fn set(args: &[String], flags: &[String]) -> i32 {
+    let (option, on) = if flags.iter().any(|f| f == "-o") {
+        (args.first(), true)
+    } else if args.first().is_some_and(|a| a == "+o") {
+        (args.get(1), false)
+    } else {
+        (None, true)
+    };
+
+    match option.map(String::as_str) {
+        Some("vi") => crate::vi::set_enabled(on),
+        Some("emacs") => crate::vi::set_enabled(!on),
+        Some(o) => return print_error(format!("unknown option {}\r\n", o)),
+        None => {
+            let vi = crate::vi::enabled();
             queue!(
                 stdout(),
-                SetForegroundColor(Color::Red),
-                Print("Error: "),
-                ResetColor,
-                Print(e)
+                Print(format!("emacs\t{}\r\n", if vi { "off" } else { "on" })),
+                Print(format!("vi\t{}\r\n", if vi { "on" } else { "off" }))
             )
             .unwrap();
-            stdout().flush().unwrap()
         }
     }
+    stdout().flush().unwrap();
+    0
 }
 
-pub fn touch(p: &Path) {
-    if p.exists() {
-        match std::fs::File::open(p) {
-            Ok(f) => match f.set_modified(SystemTime::now()) {
-                Ok(_) => {}
-                Err(e) => {
-                    queue!(
-                        stdout(),
-                        SetForegroundColor(Color::Red),
-                        Print("Error: "),
-                        ResetColor,
-                        Print(e)
-                    )
-                    .unwrap();
-                    stdout().flush().unwrap()
-                }
-            },
-            Err(e) => {
+/// bind                  List the key bindings
+/// bind -l               List the actions keys can be bound to
+/// bind KEYS ACTION      Bind KEYS, like "ctrl-x ctrl-e", to ACTION
+/// bind -x KEYS COMMAND  Run COMMAND when KEYS are pressed
+/// bind -r KEYS          Remove the binding of KEYS
+pub fn bind(args: &[String], flags: &[String]) -> i32 {
+    use crate::keymap::{self, Action, Binding};
+
+    let has = |f: &str| flags.iter().any(|x| x == f);
+    if has("-l") {
+        for name in Action::names() {
+            queue!(stdout(), Print(name), Print("\r\n")).unwrap();
+        }
+        stdout().flush().unwrap();
+        return 0;
+    }
+
+    let keys = match args.first() {
+        Some(k) => k,
+        None => {
+            for (keys, binding) in keymap::bindings() {
+                let target = match binding {
+                    Binding::Action(a) => a.name().to_string(),
+                    Binding::Command(c) => format!("-x {}", c),
+                };
                 queue!(
                     stdout(),
-                    SetForegroundColor(Color::Red),
-                    Print("Error: "),
-                    ResetColor,
-                    Print(e)
+                    Print(format!("{:<20}{}\r\n", keymap::format_keys(&keys), target))
                 )
                 .unwrap();
-                stdout().flush().unwrap()
             }
+            stdout().flush().unwrap();
+            return 0;
         }
-    } else {
-        match std::fs::File::create(p) {
-            Ok(_) => {}
-            Err(e) => {
-                queue!(
-                    stdout(),
-                    SetForegroundColor(Color::Red),
-                    Print("Error: "),
-                    ResetColor,
-                    Print(e)
-                )
-                .unwrap();
-                stdout().flush().unwrap()
+    };
+
+    let result = keymap::parse_keys(keys).and_then(|keys| {
+        if has("-r") {
+            return if keymap::unbind(&keys) {
+                Ok(())
+            } else {
+                Err(format!("{} is not bound", keymap::format_keys(&keys)))
+            };
+        }
+        let target = match args.get(1) {
+            Some(t) => t,
+            None => return Err(String::from("missing action")),
+        };
+        let binding = if has("-x") {
+            Binding::Command(args[1..].join(" "))
+        } else {
+            Binding::Action(Action::from_name(target).ok_or(format!("unknown action {}", target))?)
+        };
+        keymap::bind(keys, binding);
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        return print_error(format!("{}\r\n", e));
+    }
+    stdout().flush().unwrap();
+    0
+}
+
+/// Set by `reload`, for the shell to load the config again at the next prompt
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+pub fn reload() {
+    RELOAD.store(true, Ordering::SeqCst);
+}
+
+pub fn take_reload() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
+
+/// Line highlighted to preview a theme
+const THEME_PREVIEW: &str =
+    "cd ~/src && cargo build --release \"$TARGET\" | grep -v warning > build.log; nosuchcommand";
+
+/// List themes, preview them with `-p`, or switch to one for this session (or for good with `-s`)
+pub fn theme(args: &[String], flags: &[String]) -> i32 {
+    use crate::theme;
+
+    let has = |f: &str| flags.iter().any(|x| x == f);
+    let name = match args.first() {
+        Some(name) => name,
+        None => {
+            let active = theme::active_name();
+            for name in theme::names() {
+                if has("-p") {
+                    if let Ok(t) = theme::load(&name) {
+                        preview_theme(&t);
+                    }
+                    continue;
+                }
+                let marker = if active.as_ref() == Some(&name) {
+                    "* "
+                } else {
+                    "  "
+                };
+                queue!(stdout(), Print(marker), Print(name), Print("\r\n")).unwrap();
             }
+            stdout().flush().unwrap();
+            return 0;
+        }
+    };
+
+    let result = theme::load(name).and_then(|t| {
+        if has("-p") {
+            preview_theme(&t);
+            return Ok(());
+        }
+        theme::set_active(Some(t));
+        if has("-s") {
+            let value = ::toml::Value::String(name.clone());
+            let path = crate::toml::save_setting("theme", value).map_err(|e| e.to_string())?;
+            queue!(stdout(), Print(format!("Saved to {}\r\n", path.display()))).unwrap();
+        }
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        return print_error(format!("{}\r\n", e));
+    }
+    stdout().flush().unwrap();
+    0
+}
+
+/// The theme's name, a highlighted line with a suggestion, and its prompt colors
+fn preview_theme(t: &crate::theme::Theme) {
+    queue!(
+        stdout(),
+        SetAttribute(Attribute::Bold),
+        Print(&t.name),
+        SetAttribute(Attribute::Reset),
+        Print("\r\n  ")
+    )
+    .unwrap();
+    crate::highlight::highlight(THEME_PREVIEW, t.scheme, "\r\n");
+    queue!(
+        stdout(),
+        t.scheme.suggestion(),
+        Print(" && echo done"),
+        SetAttribute(Attribute::Reset),
+        ResetColor,
+        Print("\r\n")
+    )
+    .unwrap();
+    if !t.prompt_colors.is_empty() {
+        queue!(stdout(), Print(" ")).unwrap();
+        for (name, color) in &t.prompt_colors {
+            queue!(stdout(), Print(" "), Print(name.as_str().with(*color))).unwrap();
+        }
+        queue!(stdout(), Print("\r\n")).unwrap();
+    }
+}
+
+/// Command edited by fc, waiting to be run by the shell
+static QUEUED: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn take_queued() -> Option<String> {
+    QUEUED.lock().unwrap().take()
+}
+
+/// fc          Edit the last command in $EDITOR, then run it
+/// fc N        Edit history entry N
+/// fc -N       Edit the Nth most recent command
+/// fc PREFIX   Edit the most recent command starting with PREFIX
+/// fc -l       List the most recent commands with their numbers
+pub fn fc(args: &[String], flags: &[String]) -> i32 {
+    // The history already holds this fc command, which is not a candidate
+    let mut len = crate::history::len();
+    if len > 0
+        && crate::history::get(len - 1).is_some_and(|c| c.split_whitespace().next() == Some("fc"))
+    {
+        len -= 1;
+    }
+
+    if flags.iter().any(|f| f == "-l") {
+        for i in len.saturating_sub(16)..len {
+            if let Some(command) = crate::history::get(i) {
+                let command = command.replace('\n', "\r\n    ");
+                queue!(stdout(), Print(format!("{:<4}{}\r\n", i + 1, command))).unwrap();
+            }
+        }
+        stdout().flush().unwrap();
+        return 0;
+    }
+
+    let index = match (args.first(), flags.first()) {
+        (Some(a), _) => match a.parse::<usize>() {
+            Ok(n) => n.checked_sub(1).filter(|&i| i < len),
+            Err(_) => (0..len)
+                .rev()
+                .find(|&i| crate::history::get(i).is_some_and(|c| c.starts_with(a.as_str()))),
+        },
+        // -0 would be this fc command itself
+        (None, Some(f)) => f[1..]
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .and_then(|n| len.checked_sub(n)),
+        (None, None) => len.checked_sub(1),
+    };
+
+    let result = match index.and_then(crate::history::get) {
+        Some(command) => crate::editor::edit_externally(&command).map_err(|e| e.to_string()),
+        None => Err(String::from("no such command in history")),
+    };
+    match result {
+        // Like other shells, nothing is run when the edited command is empty
+        Ok(command) if command.trim().is_empty() => 0,
+        Ok(command) => {
+            *QUEUED.lock().unwrap() = Some(command);
+            0
         }
+        Err(e) => print_error(format!("{}\r\n", e)),
     }
 }
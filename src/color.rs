@@ -1,43 +1,404 @@
-use crossterm::style::Color;
+use crossterm::style::{Attribute, Attributes, Color, SetAttribute, SetForegroundColor};
+use crossterm::Command;
+use std::fmt;
+
+/// A color with text attributes, written in the config like "bold #ff8700"
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Style {
+    pub color: Color,
+    pub attributes: Attributes,
+}
+
+impl Style {
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            attributes: Attributes::default(),
+        }
+    }
+
+    pub fn with(mut self, attribute: Attribute) -> Self {
+        self.attributes.set(attribute);
+        self
+    }
+}
+
+/// Sets the color and attributes. They are reset with `ResetColor` and `Attribute::Reset`.
+impl Command for Style {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        SetForegroundColor(self.color).write_ansi(f)?;
+        for attribute in Attribute::iterator() {
+            if self.attributes.has(attribute) {
+                SetAttribute(attribute).write_ansi(f)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> crossterm::Result<()> {
+        SetForegroundColor(self.color).execute_winapi()
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct ColorScheme {
-    command: Color,
-    sub_command: Color,
-    string: Color,
-    flags: Color,
+    command: Style,
+    sub_command: Style,
+    string: Style,
+    flags: Style,
+    suggestion: Style,
+    error: Style,
+    operator: Style,
+    redirection: Style,
+    variable: Style,
 }
 
 impl ColorScheme {
-    pub fn new(command: Color, sub_command: Color, string: Color, flags: Color) -> Self {
+    /// Change the style of a slot, named like its config key. Returns false for unknown slots.
+    pub fn set(&mut self, slot: &str, style: Style) -> bool {
+        let slot = match slot {
+            "command" => &mut self.command,
+            "sub_command" => &mut self.sub_command,
+            "string" => &mut self.string,
+            "flags" => &mut self.flags,
+            "suggestion" => &mut self.suggestion,
+            "error" => &mut self.error,
+            "operator" => &mut self.operator,
+            "redirection" => &mut self.redirection,
+            "variable" => &mut self.variable,
+            _ => return false,
+        };
+        *slot = style;
+        true
+    }
+    /// The closest colors the terminal can show
+    pub fn downgrade(self, depth: ColorDepth) -> Self {
+        let d = |style| downgrade_style(style, depth);
         Self {
-            command,
-            sub_command,
-            string,
-            flags,
+            command: d(self.command),
+            sub_command: d(self.sub_command),
+            string: d(self.string),
+            flags: d(self.flags),
+            suggestion: d(self.suggestion),
+            error: d(self.error),
+            operator: d(self.operator),
+            redirection: d(self.redirection),
+            variable: d(self.variable),
         }
     }
-    pub fn command(&self) -> Color {
+    pub fn command(&self) -> Style {
         self.command
     }
-    pub fn sub_command(&self) -> Color {
+    pub fn sub_command(&self) -> Style {
         self.sub_command
     }
-    pub fn string(&self) -> Color {
+    pub fn string(&self) -> Style {
         self.string
     }
-    pub fn flags(&self) -> Color {
+    pub fn flags(&self) -> Style {
         self.flags
     }
+    pub fn suggestion(&self) -> Style {
+        self.suggestion
+    }
+    pub fn error(&self) -> Style {
+        self.error
+    }
+    pub fn operator(&self) -> Style {
+        self.operator
+    }
+    pub fn redirection(&self) -> Style {
+        self.redirection
+    }
+    pub fn variable(&self) -> Style {
+        self.variable
+    }
 }
 
 impl Default for ColorScheme {
     fn default() -> Self {
         Self {
-            command: Color::Yellow,
-            sub_command: Color::White,
-            string: Color::Green,
-            flags: Color::DarkGrey,
+            command: Style::new(Color::Yellow),
+            sub_command: Style::new(Color::White),
+            string: Style::new(Color::Green),
+            flags: Style::new(Color::DarkGrey),
+            suggestion: Style::new(Color::DarkGrey),
+            error: Style::new(Color::Red),
+            operator: Style::new(Color::Cyan),
+            redirection: Style::new(Color::Magenta),
+            variable: Style::new(Color::DarkCyan),
+        }
+    }
+}
+
+/// The 16 basic colors, in the order of their ANSI numbers, with their usual RGB values
+const BASIC: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (128, 0, 0)),
+    (Color::DarkGreen, (0, 128, 0)),
+    (Color::DarkYellow, (128, 128, 0)),
+    (Color::DarkBlue, (0, 0, 128)),
+    (Color::DarkMagenta, (128, 0, 128)),
+    (Color::DarkCyan, (0, 128, 128)),
+    (Color::Grey, (192, 192, 192)),
+    (Color::DarkGrey, (128, 128, 128)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (0, 0, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Steps of each component in the 6x6x6 cube of the 256 color palette
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// A color name like "darkred" or "dark_red", `#rrggbb`, `rgb(r,g,b)` or `ansi(n)`
+pub fn parse_color(color: &str) -> Option<Color> {
+    let color = color.trim().to_ascii_lowercase();
+    if let Some(hex) = color.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb {
+            r: component(0)?,
+            g: component(2)?,
+            b: component(4)?,
+        });
+    }
+    if let Some(args) = color.strip_prefix("rgb(").and_then(|c| c.strip_suffix(')')) {
+        let rgb: Vec<u8> = args
+            .split(',')
+            .map(|c| c.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        return match rgb[..] {
+            [r, g, b] => Some(Color::Rgb { r, g, b }),
+            _ => None,
+        };
+    }
+    if let Some(n) = color
+        .strip_prefix("ansi(")
+        .and_then(|c| c.strip_suffix(')'))
+    {
+        return Some(Color::AnsiValue(n.trim().parse().ok()?));
+    }
+
+    let name = color.replace(['_', '-', ' '], "").replace("gray", "grey");
+    let color = match name.as_str() {
+        "reset" => Color::Reset,
+        "white" => Color::White,
+        "black" => Color::Black,
+        "grey" => Color::Grey,
+        "red" => Color::Red,
+        "darkred" => Color::DarkRed,
+        "green" => Color::Green,
+        "darkgreen" => Color::DarkGreen,
+        "yellow" => Color::Yellow,
+        "darkyellow" => Color::DarkYellow,
+        "blue" => Color::Blue,
+        "darkblue" => Color::DarkBlue,
+        "magenta" => Color::Magenta,
+        "darkmagenta" => Color::DarkMagenta,
+        "cyan" => Color::Cyan,
+        "darkcyan" => Color::DarkCyan,
+        "darkgrey" => Color::DarkGrey,
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// A color with attributes (bold, italic, underline, dim), like "bold underline #ff8700".
+/// Without a color, white is used.
+pub fn parse_style(style: &str) -> Option<Style> {
+    // Spaces in rgb(r, g, b) don't separate words
+    let mut words = vec![String::new()];
+    let mut depth = 0;
+    for c in style.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c.is_whitespace() => {
+                if depth == 0 {
+                    words.push(String::new());
+                }
+                continue;
+            }
+            _ => {}
         }
+        words.last_mut().unwrap().push(c);
+    }
+
+    let mut parsed = Style::new(Color::White);
+    for word in words.iter().filter(|w| !w.is_empty()) {
+        let attribute = match word.to_ascii_lowercase().as_str() {
+            "bold" => Attribute::Bold,
+            "italic" => Attribute::Italic,
+            "underline" => Attribute::Underlined,
+            "dim" => Attribute::Dim,
+            _ => {
+                parsed.color = parse_color(word)?;
+                continue;
+            }
+        };
+        parsed.attributes.set(attribute);
+    }
+    Some(parsed)
+}
+
+/// How many colors the terminal can show
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// Read from COLORTERM and TERM. Terminals without TERM, like Windows ones,
+    /// are taken to show true color.
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        match std::env::var("TERM") {
+            Ok(term) if term.contains("256color") => ColorDepth::Ansi256,
+            Ok(_) => ColorDepth::Ansi16,
+            Err(_) => ColorDepth::TrueColor,
+        }
+    }
+}
+
+/// The closest color the terminal can show
+pub fn downgrade(color: Color, depth: ColorDepth) -> Color {
+    match (color, depth) {
+        (Color::Rgb { r, g, b }, ColorDepth::Ansi256) => Color::AnsiValue(nearest_256((r, g, b))),
+        (Color::Rgb { r, g, b }, ColorDepth::Ansi16) => nearest_basic((r, g, b)),
+        (Color::AnsiValue(n), ColorDepth::Ansi16) => nearest_basic(ansi_rgb(n)),
+        (color, _) => color,
+    }
+}
+
+/// The closest color the terminal can show, with the same attributes
+pub fn downgrade_style(style: Style, depth: ColorDepth) -> Style {
+    Style {
+        color: downgrade(style.color, depth),
+        ..style
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn nearest_basic(rgb: (u8, u8, u8)) -> Color {
+    BASIC
+        .iter()
+        .min_by_key(|(_, basic)| distance(rgb, *basic))
+        .unwrap()
+        .0
+}
+
+/// The closest color of the cube or the grey ramp of the 256 color palette
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    let step = |v: u8| {
+        (0..CUBE.len())
+            .min_by_key(|&i| (CUBE[i] as i32 - v as i32).abs())
+            .unwrap() as u8
+    };
+    let cube = 16 + 36 * step(rgb.0) + 6 * step(rgb.1) + step(rgb.2);
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let grey = 232 + ((average.saturating_sub(8) + 5) / 10).min(23) as u8;
+    if distance(rgb, ansi_rgb(grey)) < distance(rgb, ansi_rgb(cube)) {
+        grey
+    } else {
+        cube
+    }
+}
+
+/// RGB value of a color of the 256 color palette
+fn ansi_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => BASIC[n as usize].1,
+        16..=231 => {
+            let n = n - 16;
+            (
+                CUBE[(n / 36) as usize],
+                CUBE[(n / 6 % 6) as usize],
+                CUBE[(n % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (n - 232);
+            (level, level, level)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_colors_and_styles() {
+        assert_eq!(parse_color("Dark_Red"), Some(Color::DarkRed));
+        assert_eq!(parse_color("gray"), Some(Color::Grey));
+        assert_eq!(
+            parse_color("#ff8700"),
+            Some(Color::Rgb {
+                r: 255,
+                g: 135,
+                b: 0
+            })
+        );
+        assert_eq!(
+            parse_color("rgb(1, 2, 3)"),
+            Some(Color::Rgb { r: 1, g: 2, b: 3 })
+        );
+        assert_eq!(parse_color("ansi(208)"), Some(Color::AnsiValue(208)));
+        assert_eq!(parse_color("#ff87"), None);
+        assert_eq!(parse_color("rgb(1,2)"), None);
+        assert_eq!(parse_color("redd"), None);
+
+        assert_eq!(
+            parse_style("italic rgb(1, 2, 3)"),
+            Some(Style::new(Color::Rgb { r: 1, g: 2, b: 3 }).with(Attribute::Italic))
+        );
+        let style = parse_style("bold underline cyan").unwrap();
+        assert_eq!(style.color, Color::Cyan);
+        assert!(style.attributes.has(Attribute::Bold));
+        assert!(style.attributes.has(Attribute::Underlined));
+        assert!(!style.attributes.has(Attribute::Italic));
+        assert_eq!(parse_style("bold blinking"), None);
+    }
+
+    #[test]
+    fn downgrade_colors() {
+        let orange = Color::Rgb {
+            r: 255,
+            g: 135,
+            b: 0,
+        };
+        assert_eq!(downgrade(orange, ColorDepth::TrueColor), orange);
+        assert_eq!(
+            downgrade(orange, ColorDepth::Ansi256),
+            Color::AnsiValue(208)
+        );
+        assert_eq!(downgrade(orange, ColorDepth::Ansi16), Color::Yellow);
+        let grey = Color::Rgb {
+            r: 100,
+            g: 100,
+            b: 100,
+        };
+        assert_eq!(downgrade(grey, ColorDepth::Ansi256), Color::AnsiValue(241));
+        assert_eq!(
+            downgrade(Color::AnsiValue(196), ColorDepth::Ansi16),
+            Color::Red
+        );
+        assert_eq!(downgrade(Color::Cyan, ColorDepth::Ansi16), Color::Cyan);
     }
 }
@@ -1,15 +1,19 @@
 use crate::error::{CommandParseError, ErrorKind};
+use crate::lexer::{tokenize, Token, TokenKind};
 use crossterm::style::*;
 use crossterm::*;
 use futures::io::BufReader;
 use futures::{AsyncBufReadExt, StreamExt};
 use std::io::{stdout, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 
 use crate::CommandType;
 
-const BUILTIN_COMMAND_NAME: [&str; 7] = ["cd", "cp", "exit", "help", "rm", "rmdir", "touch"];
+pub const BUILTIN_COMMAND_NAME: [&str; 13] = [
+    "bind", "cd", "complete", "cp", "exit", "fc", "help", "reload", "rm", "rmdir", "set", "theme",
+    "touch",
+];
 
 pub struct ParsedCommand {
     pub command: String,
@@ -28,7 +32,7 @@ impl ParsedCommand {
     }
 
     #[tokio::main]
-    pub async fn run(&self) -> std::result::Result<(), std::io::Error> {
+    pub async fn run(&self) -> std::result::Result<std::process::ExitStatus, std::io::Error> {
         use async_process::Command;
         let r_child = if self.subcommand.is_empty() && !self.flags.is_empty() {
             Command::new(&self.command)
@@ -56,7 +60,7 @@ impl ParsedCommand {
             println!("{}", line.unwrap());
         }
 
-        Ok(())
+        child.status().await
     }
 }
 
@@ -76,45 +80,41 @@ impl BuiltinCommand {
         }
     }
 
+    /// Run the builtin and give its exit status, 1 when it failed
     #[tokio::main]
-    pub async fn run(&self) {
+    pub async fn run(&self) -> i32 {
         match self.command.as_str() {
+            "bind" => crate::builtin::bind(&self.subcommand, &self.flags),
+
             "cd" => {
                 let p = {
-                    if self.subcommand.len() == 0 {
+                    if self.subcommand.is_empty() {
                         dirs::home_dir().unwrap()
                     } else {
                         Path::new(&self.subcommand[0]).to_path_buf()
                     }
                 };
                 match std::env::set_current_dir(p) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        execute!(
-                            stdout(),
-                            SetForegroundColor(Color::Red),
-                            Print("Error: "),
-                            ResetColor,
-                            Print(e)
-                        )
-                        .unwrap();
-                    }
+                    Ok(_) => 0,
+                    Err(e) => crate::builtin::print_error(e),
                 }
             }
 
-            "cp" => {
-                if self.subcommand.len() >= 2 {
-                    crate::builtin::cp(
-                        Path::new(&self.subcommand[0]),
-                        Path::new(&self.subcommand[1]),
-                    );
-                }
-            }
+            "complete" => crate::builtin::complete(&self.subcommand, &self.flags),
+
+            "cp" if self.subcommand.len() >= 2 => crate::builtin::cp(
+                Path::new(&self.subcommand[0]),
+                Path::new(&self.subcommand[1]),
+            ),
+            "cp" => crate::builtin::print_error("usage: cp SOURCE DEST\r\n"),
 
             "exit" => {
+                crate::restore_terminal();
                 std::process::exit(0);
             }
 
+            "fc" => crate::builtin::fc(&self.subcommand, &self.flags),
+
             "help" => {
                 queue!(
                     stdout(),
@@ -127,63 +127,243 @@ impl BuiltinCommand {
                 )
                 .unwrap();
                 stdout().flush().unwrap();
+                0
             }
 
-            "rm" => {
-                for s in &self.subcommand {
-                    crate::builtin::rm(Path::new(&s));
-                }
+            "reload" => {
+                crate::builtin::reload();
+                0
             }
 
-            "rmdir" => {
-                for s in &self.subcommand {
-                    crate::builtin::rmdir(Path::new(&s));
-                }
-            }
+            // Every file is tried, and the status tells whether one failed
+            "rm" => self
+                .subcommand
+                .iter()
+                .map(|s| crate::builtin::rm(Path::new(s)))
+                .max()
+                .unwrap_or(0),
 
-            "touch" => {
-                for s in &self.subcommand {
-                    crate::builtin::touch(Path::new(&s));
-                }
+            "rmdir" => self
+                .subcommand
+                .iter()
+                .map(|s| crate::builtin::rmdir(Path::new(s)))
+                .max()
+                .unwrap_or(0),
+
+            "set" => crate::builtin::set(&self.subcommand, &self.flags),
+
+            "theme" => crate::builtin::theme(&self.subcommand, &self.flags),
+
+            "touch" => self
+                .subcommand
+                .iter()
+                .map(|s| crate::builtin::touch(Path::new(s)))
+                .max()
+                .unwrap_or(0),
+
+            _ => 0,
+        }
+    }
+}
+
+pub fn is_builtin(command: &str) -> bool {
+    BUILTIN_COMMAND_NAME.contains(&command.to_ascii_lowercase().as_str())
+}
+
+/// Look up an executable in the directories of PATH
+pub fn find_in_path(command: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).find_map(|dir| {
+        let candidate = dir.join(command);
+        if is_executable(&candidate) {
+            return Some(candidate);
+        }
+        #[cfg(windows)]
+        for ext in std::env::var("PATHEXT")
+            .unwrap_or_else(|_| String::from(".EXE;.BAT;.CMD"))
+            .split(';')
+        {
+            let candidate = dir.join(format!("{}{}", command, ext));
+            if candidate.is_file() {
+                return Some(candidate);
             }
+        }
+        None
+    })
+}
+
+#[cfg(unix)]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(windows)]
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Whether `input` needs more lines before it can run:
+/// an open quote, a trailing backslash or operator, or an unclosed block
+pub fn is_incomplete(input: &str) -> bool {
+    let tokens = tokenize(input);
+    if tokens.iter().any(|t| t.unterminated.is_some()) {
+        return true;
+    }
+    if input.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1 {
+        return true;
+    }
+    if let Some(t) = tokens.iter().rev().find(|t| t.text != "\n") {
+        if t.kind == TokenKind::Operator && matches!(t.text.as_str(), "|" | "&&" | "||") {
+            return true;
+        }
+    }
 
+    // Keywords only count as the first word of a command
+    let mut depth = 0;
+    let mut command_position = true;
+    for t in &tokens {
+        if t.kind != TokenKind::Word {
+            command_position = t.kind == TokenKind::Operator;
+            continue;
+        }
+        if !command_position || !t.quotes.is_empty() {
+            command_position = false;
+            continue;
+        }
+        match t.text.as_str() {
+            "if" | "for" | "while" | "until" | "case" | "{" => depth += 1,
+            "fi" | "done" | "esac" | "}" => depth -= 1,
             _ => {}
         }
+        command_position = matches!(
+            t.text.as_str(),
+            "if" | "then" | "else" | "elif" | "do" | "while" | "until" | "{" | "!"
+        );
     }
+    depth > 0
 }
 
-pub fn parse_command(original: &str) -> std::result::Result<CommandType, CommandParseError> {
-    let mut subcommand: Vec<String> = vec![];
-    let mut flags: Vec<String> = vec![];
+/// Words starting compound commands, or parts of them
+const KEYWORDS: [&str; 14] = [
+    "if", "then", "elif", "else", "fi", "for", "while", "until", "do", "done", "case", "esac", "{",
+    "}",
+];
 
-    let mut divided: Vec<&str> = original.split_ascii_whitespace().collect();
+/// Parse a command line into its commands, one per line, in the order they run
+pub fn parse_command(original: &str) -> std::result::Result<Vec<CommandType>, CommandParseError> {
+    let tokens = tokenize(original);
+    if tokens.iter().any(|t| t.unterminated.is_some()) {
+        return Err(CommandParseError::simple(ErrorKind::UnterminatedQuote));
+    }
 
-    let len = divided.len();
+    let lines: Vec<&[Token]> = tokens
+        .split(|t| t.kind == TokenKind::Operator && t.text == "\n")
+        .filter(|line| !line.is_empty())
+        .collect();
+    // Only simple commands can run. Nothing is run when the input holds anything else,
+    // since running its lines one by one would ignore the conditions between them.
+    for line in &lines {
+        let keyword = line[0].quotes.is_empty() && KEYWORDS.contains(&line[0].text.as_str());
+        if keyword || line.iter().any(|t| t.kind != TokenKind::Word) {
+            return Err(CommandParseError::simple(ErrorKind::Unsupported));
+        }
+    }
 
-    let command: String = if len == 0 {
+    let commands: Vec<CommandType> = lines.into_iter().map(parse_line).collect();
+    if commands.is_empty() {
         return Err(CommandParseError::simple(ErrorKind::Null));
-    } else {
-        divided[0].parse().unwrap()
-    };
-    divided.remove(0);
-
-    for i in divided {
-        if i.starts_with('-') {
-            flags.push(i.parse().unwrap());
+    }
+    Ok(commands)
+}
+
+/// Parse the tokens of a single command
+fn parse_line(tokens: &[Token]) -> CommandType {
+    let mut subcommand: Vec<String> = vec![];
+    let mut flags: Vec<String> = vec![];
+
+    let command = tokens[0].text.clone();
+
+    for i in &tokens[1..] {
+        if i.text.starts_with('-') {
+            flags.push(i.text.clone());
         } else {
-            subcommand.push(i.parse().unwrap());
+            subcommand.push(i.text.clone());
         }
     }
 
     for i in BUILTIN_COMMAND_NAME {
         if command.to_ascii_lowercase() == i {
-            return Ok(CommandType::Builtin(BuiltinCommand::new(
-                command, subcommand, flags,
-            )));
+            return CommandType::Builtin(BuiltinCommand::new(command, subcommand, flags));
         }
     }
 
-    Ok(CommandType::Executable(ParsedCommand::new(
-        command, subcommand, flags,
-    )))
+    CommandType::Executable(ParsedCommand::new(command, subcommand, flags))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn incomplete_input() {
+        assert!(is_incomplete("echo \"hello"));
+        assert!(is_incomplete("ls \\"));
+        assert!(is_incomplete("ls |"));
+        assert!(is_incomplete("cargo build &&\n"));
+        assert!(is_incomplete("if true; then\n echo a"));
+        assert!(is_incomplete("for i in a b; do echo { ; done; {"));
+        assert!(!is_incomplete("if true; then echo a; fi"));
+        assert!(!is_incomplete("echo \"{\" if"));
+        assert!(!is_incomplete("ls \\\\"));
+    }
+
+    #[test]
+    fn compound_commands_are_not_run() {
+        for input in [
+            "if false; then\nrm x\nfi",
+            "if false\nthen\nrm x\nfi",
+            "cargo build &&\ncargo test",
+            "ls |\ngrep x",
+            "echo a > out",
+        ] {
+            assert!(parse_command(input).is_err(), "{:?}", input);
+        }
+        assert_eq!(parse_command("echo 'if' \"|\"").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn commands_per_line() {
+        let commands = parse_command("a\nb").unwrap();
+        let names: Vec<&str> = commands
+            .iter()
+            .map(|c| match c {
+                CommandType::Executable(e) => e.command.as_str(),
+                CommandType::Builtin(b) => b.command.as_str(),
+            })
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+
+        match &parse_command("cd foo\n\nmake -j4").unwrap()[..] {
+            [CommandType::Builtin(cd), CommandType::Executable(make)] => {
+                assert_eq!(cd.subcommand, ["foo"]);
+                assert_eq!(make.flags, ["-j4"]);
+            }
+            _ => panic!("expected cd and make"),
+        }
+        assert!(parse_command("\n\n").is_err());
+        assert_eq!(parse_command("ls \\\n-l").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failed_builtin_status() {
+        match parse_command("cd /no/such/xcys/dir").unwrap().remove(0) {
+            CommandType::Builtin(cd) => assert_eq!(cd.run(), 1),
+            _ => panic!("cd is a builtin"),
+        }
+        match parse_command("cp onlyone").unwrap().remove(0) {
+            CommandType::Builtin(cp) => assert_eq!(cp.run(), 1),
+            _ => panic!("cp is a builtin"),
+        }
+    }
 }
@@ -0,0 +1,570 @@
+use crate::command::BUILTIN_COMMAND_NAME;
+use crate::grapheme;
+use crate::lexer::{escape, tokenize, Token, TokenKind};
+use crate::toml::CompletionSpec;
+use crossterm::cursor::{MoveToColumn, MoveUp};
+use crossterm::queue;
+use crossterm::style::*;
+use std::io::stdout;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Specs shipped with XCYS. Files in the completions directory
+/// with the same `command` take precedence.
+const BUNDLED_SPECS: [&str; 3] = [
+    include_str!("../res/completions/cargo.toml"),
+    include_str!("../res/completions/docker.toml"),
+    include_str!("../res/completions/git.toml"),
+];
+
+static SPECS: Mutex<Vec<CompletionSpec>> = Mutex::new(vec![]);
+
+/// Register the bundled specs and the ones in the completions directory
+pub fn load_specs() {
+    for contents in BUNDLED_SPECS {
+        if let Ok(spec) = crate::toml::parse_completion_spec(contents) {
+            register(spec);
+        }
+    }
+    for spec in crate::toml::load_completion_specs() {
+        SPECS.lock().unwrap().retain(|s| s.command != spec.command);
+        register(spec);
+    }
+}
+
+/// Add a spec, merging it into an already registered spec for the same command
+pub fn register(spec: CompletionSpec) {
+    let mut specs = SPECS.lock().unwrap();
+    let existing = match specs.iter_mut().find(|s| s.command == spec.command) {
+        Some(s) => s,
+        None => {
+            specs.push(spec);
+            return;
+        }
+    };
+
+    extend_unique(&mut existing.flags, spec.flags);
+    if spec.dynamic.is_some() {
+        existing.dynamic = spec.dynamic;
+    }
+    for sub in spec.subcommands {
+        match existing.subcommands.iter_mut().find(|s| s.name == sub.name) {
+            Some(e) => {
+                extend_unique(&mut e.flags, sub.flags);
+                if sub.dynamic.is_some() {
+                    e.dynamic = sub.dynamic;
+                }
+            }
+            None => existing.subcommands.push(sub),
+        }
+    }
+}
+
+fn extend_unique(v: &mut Vec<String>, items: Vec<String>) {
+    for i in items {
+        if !v.contains(&i) {
+            v.push(i);
+        }
+    }
+}
+
+pub fn specs() -> Vec<CompletionSpec> {
+    SPECS.lock().unwrap().clone()
+}
+
+pub struct Candidate {
+    /// Text inserted into the command line (already escaped)
+    pub replacement: String,
+    /// Text shown in the completion menu
+    pub display: String,
+}
+
+pub struct Completion {
+    /// Byte offset where the word being completed starts
+    pub start: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+pub fn complete(input: &str) -> Completion {
+    let mut tokens = tokenize(input);
+    // Only the command after the last operator matters
+    if let Some(i) = tokens.iter().rposition(|t| t.kind == TokenKind::Operator) {
+        tokens.drain(..=i);
+    }
+
+    // The word under the cursor. If the input ends with (unescaped) whitespace,
+    // a new empty word is being started.
+    let (index, start, word) = match tokens.last() {
+        Some(t) if t.end == input.len() && t.kind == TokenKind::Word => {
+            (tokens.len() - 1, t.start, t.text.clone())
+        }
+        _ => (tokens.len(), input.len(), String::new()),
+    };
+    let after_redirect = index > 0 && tokens[index - 1].kind == TokenKind::Redirect;
+    let raw = &input[start..];
+
+    let mut candidates = if let Some(name) = raw.strip_prefix('$') {
+        variables(name)
+    } else if index == 0 && !word.contains('/') && !word.contains('\\') {
+        commands(&word)
+    } else if after_redirect {
+        paths(&word)
+    } else {
+        let mut c = from_spec(&tokens, index, &word).unwrap_or_default();
+        if !word.starts_with('-') {
+            c.append(&mut paths(&word));
+        }
+        c
+    };
+
+    candidates.sort_by(|a, b| a.display.cmp(&b.display));
+    candidates.dedup_by(|a, b| a.display == b.display);
+
+    Completion { start, candidates }
+}
+
+/// Candidates from the spec registered for the command being completed
+fn from_spec(tokens: &[Token], index: usize, word: &str) -> Option<Vec<Candidate>> {
+    let command = &tokens.first()?.text;
+    let spec = specs().into_iter().find(|s| &s.command == command)?;
+    let sub = tokens
+        .get(1)
+        .filter(|_| index > 1)
+        .and_then(|t| spec.subcommands.iter().find(|s| s.name == t.text));
+
+    let mut words: Vec<String> = vec![];
+    if word.starts_with('-') {
+        if let Some(sub) = sub {
+            words.extend(sub.flags.iter().cloned());
+        }
+        words.extend(spec.flags.iter().cloned());
+    } else {
+        if index == 1 {
+            words.extend(spec.subcommands.iter().map(|s| s.name.clone()));
+        }
+        let dynamic = match sub {
+            Some(sub) => sub.dynamic.as_ref(),
+            None => spec.dynamic.as_ref(),
+        };
+        if let Some(d) = dynamic {
+            words.extend(run_dynamic(d));
+        }
+    }
+
+    Some(
+        words
+            .into_iter()
+            .filter(|w| w.starts_with(word))
+            .map(|w| Candidate {
+                replacement: escape(&w),
+                display: w,
+            })
+            .collect(),
+    )
+}
+
+/// How long a dynamic completion command may run before it is killed
+const DYNAMIC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Run `command` and use each line of its output as a candidate.
+/// It can't read the terminal, and gives nothing when it takes too long.
+fn run_dynamic(command: &str) -> Vec<String> {
+    let tokens = tokenize(command);
+    let (program, args) = match tokens.split_first() {
+        Some(t) => t,
+        None => return vec![],
+    };
+    let output = crate::prompt::runtime().block_on(async {
+        let child = tokio::process::Command::new(&program.text)
+            .args(args.iter().map(|t| &t.text))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .ok()?;
+        tokio::time::timeout(DYNAMIC_TIMEOUT, child.wait_with_output())
+            .await
+            .ok()?
+            .ok()
+    });
+    let output = match output {
+        Some(o) => o,
+        None => return vec![],
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+fn variables(prefix: &str) -> Vec<Candidate> {
+    std::env::vars()
+        .filter(|(k, _)| k.starts_with(prefix))
+        .map(|(k, _)| Candidate {
+            replacement: format!("${}", k),
+            display: k,
+        })
+        .collect()
+}
+
+fn commands(prefix: &str) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = BUILTIN_COMMAND_NAME
+        .iter()
+        .filter(|b| b.starts_with(prefix))
+        .map(|b| Candidate {
+            replacement: b.to_string(),
+            display: b.to_string(),
+        })
+        .collect();
+
+    let path = match std::env::var_os("PATH") {
+        Some(p) => p,
+        None => return candidates,
+    };
+
+    for dir in std::env::split_paths(&path) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let name = match entry.file_name().into_string() {
+                Ok(n) => n,
+                Err(_) => continue,
+            };
+            if !name.starts_with(prefix) {
+                continue;
+            }
+            if let Some(name) = executable_name(&entry.path(), name) {
+                candidates.push(Candidate {
+                    replacement: escape(&name),
+                    display: name,
+                });
+            }
+        }
+    }
+
+    candidates
+}
+
+#[cfg(unix)]
+fn executable_name(path: &Path, name: String) -> Option<String> {
+    crate::command::is_executable(path).then_some(name)
+}
+
+#[cfg(windows)]
+fn executable_name(path: &Path, name: String) -> Option<String> {
+    let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| String::from(".EXE;.BAT;.CMD"));
+    let extension = format!(".{}", path.extension()?.to_str()?);
+    if !path.is_file() {
+        return None;
+    }
+    pathext
+        .split(';')
+        .any(|e| e.eq_ignore_ascii_case(&extension))
+        .then(|| name[..name.len() - extension.len()].to_string())
+}
+
+fn paths(word: &str) -> Vec<Candidate> {
+    // Split "dir/fi" into the directory to read and the file name prefix
+    let (dir, prefix) = match word.rfind(['/', '\\']) {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+
+    let read_from: PathBuf = if dir.is_empty() {
+        PathBuf::from(".")
+    } else if let Some(rest) = dir.strip_prefix('~') {
+        let mut home = dirs::home_dir().unwrap_or_default().into_os_string();
+        home.push(rest);
+        PathBuf::from(home)
+    } else {
+        PathBuf::from(dir)
+    };
+
+    let entries = match std::fs::read_dir(read_from) {
+        Ok(e) => e,
+        Err(_) => return vec![],
+    };
+
+    let mut candidates = vec![];
+    for entry in entries.flatten() {
+        let name = match entry.file_name().into_string() {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+        // Hidden files are only offered when explicitly asked for
+        if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+            continue;
+        }
+        let mut display = name.clone();
+        if entry.path().is_dir() {
+            display.push('/');
+        }
+        // Keep a leading "~" unescaped so that it is still expanded
+        let replacement = match dir.strip_prefix('~') {
+            Some(rest) => format!("~{}", escape(&format!("{}{}", rest, display))),
+            None => escape(&format!("{}{}", dir, display)),
+        };
+        candidates.push(Candidate {
+            replacement,
+            display,
+        });
+    }
+
+    candidates
+}
+
+/// Longest prefix shared by every candidate's replacement
+pub fn common_prefix(candidates: &[Candidate]) -> String {
+    let mut prefix = match candidates.first() {
+        Some(c) => c.replacement.clone(),
+        None => return String::new(),
+    };
+    for c in &candidates[1..] {
+        let len = prefix
+            .char_indices()
+            .zip(c.replacement.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8());
+        prefix.truncate(len);
+    }
+    prefix
+}
+
+/// A multi-column completion menu shown below the input line.
+/// Candidates are laid out column by column, like `ls`.
+pub struct Menu {
+    pub completion: Completion,
+    /// The input line before any candidate was selected
+    pub original: String,
+    /// Cursor position in `original`, the end of the word being completed
+    pub cursor: usize,
+    pub selected: Option<usize>,
+    first_row: usize,
+    /// Screen row of the first line of the menu, once drawn
+    pub top: u16,
+    /// Menu rows that fit on screen
+    visible: usize,
+}
+
+impl Menu {
+    pub fn new(completion: Completion, original: String, cursor: usize) -> Self {
+        Self {
+            completion,
+            original,
+            cursor,
+            selected: None,
+            first_row: 0,
+            top: 0,
+            visible: 0,
+        }
+    }
+
+    /// Input line and cursor with the selected candidate applied
+    pub fn apply(&self) -> (String, usize) {
+        let mut input = self.original.clone();
+        match self.selected {
+            Some(i) => {
+                let replacement = &self.completion.candidates[i].replacement;
+                input.replace_range(self.completion.start..self.cursor, replacement);
+                (input, self.completion.start + replacement.len())
+            }
+            None => (input, self.cursor),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.completion.candidates.len()
+    }
+
+    fn column_width(&self) -> usize {
+        self.completion
+            .candidates
+            .iter()
+            .map(|c| grapheme::width(&c.display))
+            .max()
+            .unwrap_or(0)
+            + 2
+    }
+
+    fn layout(&self) -> (usize, usize) {
+        let width = crossterm::terminal::size().map_or(80, |s| s.0 as usize);
+        let columns = (width / self.column_width()).max(1);
+        let rows = self.len().div_ceil(columns);
+        (columns, rows)
+    }
+
+    pub fn next(&mut self) {
+        self.selected = Some(match self.selected {
+            Some(i) => (i + 1) % self.len(),
+            None => 0,
+        });
+    }
+
+    pub fn previous(&mut self) {
+        self.selected = Some(match self.selected {
+            Some(0) | None => self.len() - 1,
+            Some(i) => i - 1,
+        });
+    }
+
+    pub fn left(&mut self) {
+        let rows = self.layout().1;
+        match self.selected {
+            Some(i) if i >= rows => self.selected = Some(i - rows),
+            None => self.previous(),
+            _ => {}
+        }
+    }
+
+    pub fn right(&mut self) {
+        let rows = self.layout().1;
+        match self.selected {
+            Some(i) if i + rows < self.len() => self.selected = Some(i + rows),
+            None => self.next(),
+            _ => {}
+        }
+    }
+
+    /// Candidate shown at a screen position
+    pub fn candidate_at(&self, column: u16, row: u16) -> Option<usize> {
+        let (columns, rows) = self.layout();
+        let row = row.checked_sub(self.top)? as usize;
+        let col = column as usize / self.column_width();
+        if row >= self.visible || col >= columns {
+            return None;
+        }
+        let i = col * rows + self.first_row + row;
+        (i < self.len()).then_some(i)
+    }
+
+    /// Draw the menu below the current line and put the cursor back where it was
+    pub fn render(&mut self) {
+        let (columns, rows) = self.layout();
+        let width = self.column_width();
+        let (column, _) = crossterm::cursor::position().unwrap_or((0, 0));
+        let height = crossterm::terminal::size().map_or(24, |s| s.1 as usize);
+        let visible = rows.min(height.saturating_sub(2).max(1));
+        self.visible = visible;
+
+        // Scroll so that the selected candidate is always visible
+        if let Some(i) = self.selected {
+            let row = i % rows;
+            if row < self.first_row {
+                self.first_row = row;
+            } else if row >= self.first_row + visible {
+                self.first_row = row + 1 - visible;
+            }
+        }
+
+        for row in self.first_row..self.first_row + visible {
+            queue!(stdout(), Print("\r\n")).unwrap();
+            for col in 0..columns {
+                let i = col * rows + row;
+                let candidate = match self.completion.candidates.get(i) {
+                    Some(c) => c,
+                    None => break,
+                };
+                // Padded by display width so that wide characters line up
+                let padding = width.saturating_sub(grapheme::width(&candidate.display));
+                let text = format!("{}{}", candidate.display, " ".repeat(padding));
+                if self.selected == Some(i) {
+                    queue!(
+                        stdout(),
+                        SetAttribute(Attribute::Reverse),
+                        Print(&text[..text.len() - 2]),
+                        SetAttribute(Attribute::Reset),
+                        Print("  ")
+                    )
+                    .unwrap();
+                } else {
+                    queue!(stdout(), Print(text)).unwrap();
+                }
+            }
+        }
+
+        queue!(stdout(), MoveUp(visible as u16), MoveToColumn(column)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn common_prefix_of_candidates() {
+        let candidates: Vec<Candidate> = ["cargo", "cargo-clippy", "carton"]
+            .iter()
+            .map(|s| Candidate {
+                replacement: s.to_string(),
+                display: s.to_string(),
+            })
+            .collect();
+        assert_eq!(common_prefix(&candidates), "car");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dynamic_commands_time_out() {
+        assert_eq!(run_dynamic("echo a"), ["a"]);
+        let started = std::time::Instant::now();
+        assert!(run_dynamic("sleep 10").is_empty());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn bundled_specs_parse() {
+        for contents in BUNDLED_SPECS {
+            assert!(crate::toml::parse_completion_spec(contents).is_ok());
+        }
+    }
+
+    #[test]
+    fn complete_from_spec() {
+        // The bundled spec only, as load_specs would also read the user's completions directory
+        register(crate::toml::parse_completion_spec(BUNDLED_SPECS[0]).unwrap());
+        let completion = complete("cargo build --rel");
+        assert_eq!(completion.start, 12);
+        assert!(completion
+            .candidates
+            .iter()
+            .any(|c| c.display == "--release"));
+    }
+
+    #[test]
+    fn complete_builtins() {
+        let completion = complete("rm");
+        assert_eq!(completion.start, 0);
+        assert!(completion.candidates.iter().any(|c| c.display == "rmdir"));
+    }
+
+    #[test]
+    fn menu_candidate_at() {
+        let candidates = ["aa", "bb", "cc"]
+            .iter()
+            .map(|s| Candidate {
+                replacement: s.to_string(),
+                display: s.to_string(),
+            })
+            .collect();
+        let mut menu = Menu::new(
+            Completion {
+                start: 0,
+                candidates,
+            },
+            String::new(),
+            0,
+        );
+        menu.top = 10;
+        menu.visible = 1;
+        assert_eq!(menu.candidate_at(5, 10), Some(1));
+        assert_eq!(menu.candidate_at(0, 11), None);
+        assert_eq!(menu.candidate_at(13, 10), None);
+    }
+}
@@ -0,0 +1,995 @@
+use crate::color::ColorScheme;
+use crate::completion::{self, Menu};
+use crate::grapheme;
+use crate::highlight::highlight;
+use crate::history;
+use crate::keymap::Action;
+use crate::kill_ring;
+use crate::vi::{self, Mode, Vi};
+use crossterm::cursor::{MoveTo, SetCursorStyle};
+use crossterm::event::{KeyCode, MouseButton, MouseEvent, MouseEventKind};
+use crossterm::queue;
+use crossterm::style::*;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType};
+use std::io::{stdout, Write};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Shown at the start of every line after the first one
+pub const CONTINUATION_PROMPT: &str = "> ";
+
+/// Replaces the prompt of a submitted line when the transient prompt is on
+pub const TRANSIENT_PROMPT: &str = "$ ";
+
+/// The line being edited at the prompt.
+/// `cursor` is a byte offset into `buffer`.
+pub struct Editor {
+    pub buffer: String,
+    pub cursor: usize,
+    scheme: ColorScheme,
+    /// Printed before the input. It may span several lines and carry styles.
+    prompt: String,
+    /// Shown at the right end of the first input row while the input doesn't reach it
+    pub right_prompt: String,
+    /// Collapse the prompt to TRANSIENT_PROMPT once the line is submitted
+    pub transient: bool,
+    /// Rows the prompt takes above the one where the input starts
+    prompt_rows: usize,
+    /// Column where the input starts, right after the prompt
+    prompt_width: u16,
+    /// Row where the prompt starts
+    row: u16,
+    menu: Option<Menu>,
+    /// Position in the history while browsing it with Up/Down
+    history_index: Option<usize>,
+    /// The line as typed before browsing the history
+    saved: String,
+    /// Don't draw the autosuggestion (when the line is submitted)
+    hide_suggestion: bool,
+    /// Text of several lines has been pasted
+    pub pasted_lines: bool,
+    /// States of the line before each change, for undo
+    undo: Vec<(String, usize)>,
+    /// States undone since the last change, for redo
+    redo: Vec<(String, usize)>,
+    /// What the previous key did, so that typing and kills can be grouped
+    last: Last,
+    /// Where the last yanked text is, to replace it when rotating the kill ring
+    yanked: (usize, usize),
+    pub vi: Vi,
+}
+
+impl Editor {
+    /// Start editing on the current row, after `prompt`
+    pub fn new(scheme: ColorScheme, prompt: &str) -> Self {
+        let row = crossterm::cursor::position().map_or(0, |p| p.1);
+        Self {
+            buffer: String::new(),
+            cursor: 0,
+            scheme,
+            prompt: prompt.to_string(),
+            right_prompt: String::new(),
+            transient: false,
+            prompt_rows: 0,
+            prompt_width: 0,
+            row,
+            menu: None,
+            history_index: None,
+            saved: String::new(),
+            hide_suggestion: false,
+            pasted_lines: false,
+            undo: vec![],
+            redo: vec![],
+            last: Last::Other,
+            yanked: (0, 0),
+            vi: Vi::default(),
+        }
+    }
+
+    /// Remember the current state of the line so that the next change can be undone
+    pub fn save_undo(&mut self) {
+        self.push_undo(self.buffer.clone(), self.cursor);
+    }
+
+    fn push_undo(&mut self, buffer: String, cursor: usize) {
+        if self.undo.last().map(|u| &u.0) != Some(&buffer) {
+            self.undo.push((buffer, cursor));
+        }
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self) {
+        if let Some((buffer, cursor)) = self.undo.pop() {
+            let current = std::mem::replace(&mut self.buffer, buffer);
+            self.redo.push((current, self.cursor));
+            self.cursor = cursor;
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some((buffer, cursor)) = self.redo.pop() {
+            let current = std::mem::replace(&mut self.buffer, buffer);
+            self.undo.push((current, self.cursor));
+            self.cursor = cursor;
+        }
+    }
+
+    /// Insert a character typed at the prompt.
+    /// In emacs mode a word typed in one go is undone at once.
+    pub fn type_char(&mut self, c: char) {
+        if !vi::enabled() && (self.last != Last::Typed || c.is_whitespace()) {
+            self.save_undo();
+        }
+        self.insert(c);
+        self.last = Last::Typed;
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.buffer.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Insert pasted text at the cursor. Newlines are kept in the buffer
+    /// rather than running the command.
+    pub fn paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        // A line copied with its line break shouldn't leave an empty line behind
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        if text.contains('\n') {
+            self.pasted_lines = true;
+        }
+        self.save_undo();
+        self.last = Last::Other;
+        self.buffer.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    /// Delete the grapheme before the cursor, with any accents or joined emoji
+    pub fn backspace(&mut self) {
+        let start = grapheme::previous(&self.buffer, self.cursor);
+        self.delete_range(start, self.cursor);
+    }
+
+    pub fn delete(&mut self) {
+        let end = grapheme::next(&self.buffer, self.cursor);
+        self.buffer.replace_range(self.cursor..end, "");
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = grapheme::previous(&self.buffer, self.cursor);
+    }
+
+    /// Move right, or accept the autosuggestion at the end of the line
+    pub fn move_right(&mut self) {
+        if self.cursor == self.buffer.len() {
+            self.accept_suggestion();
+        } else {
+            self.cursor = grapheme::next(&self.buffer, self.cursor);
+        }
+    }
+
+    pub fn line_start(&self) -> usize {
+        self.buffer[..self.cursor].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    pub fn line_end(&self) -> usize {
+        self.buffer[self.cursor..]
+            .find('\n')
+            .map_or(self.buffer.len(), |i| self.cursor + i)
+    }
+
+    /// Move to the start of the current line
+    pub fn home(&mut self) {
+        self.cursor = self.line_start();
+    }
+
+    /// Move to the end of the current line,
+    /// or accept the autosuggestion if already at the end of the input
+    pub fn end(&mut self) {
+        if self.cursor == self.buffer.len() {
+            self.accept_suggestion();
+            self.cursor = self.buffer.len();
+        } else {
+            self.cursor = self.line_end();
+        }
+    }
+
+    /// Move to the same column of the previous line.
+    /// Returns false on the first line.
+    pub fn move_up(&mut self) -> bool {
+        let start = self.line_start();
+        if start == 0 {
+            return false;
+        }
+        let column = grapheme::width(&self.buffer[start..self.cursor]);
+        let previous = self.buffer[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+        self.cursor = grapheme::column_offset(&self.buffer[previous..start - 1], column) + previous;
+        true
+    }
+
+    /// Move to the same column of the next line.
+    /// Returns false on the last line.
+    pub fn move_down(&mut self) -> bool {
+        let end = self.line_end();
+        if end == self.buffer.len() {
+            return false;
+        }
+        let column = grapheme::width(&self.buffer[self.line_start()..self.cursor]);
+        let next = end + 1;
+        let next_end = self.buffer[next..]
+            .find('\n')
+            .map_or(self.buffer.len(), |i| next + i);
+        self.cursor = grapheme::column_offset(&self.buffer[next..next_end], column) + next;
+        true
+    }
+
+    /// Replace the prompt, as when a slow segment of it has been computed
+    pub fn set_prompt(&mut self, prompt: String) {
+        self.prompt = prompt;
+    }
+
+    /// Replace the whole line and put the cursor at its end
+    pub fn set_buffer(&mut self, s: String) {
+        self.buffer = s;
+        self.cursor = self.buffer.len();
+    }
+
+    /// Most recent history entry continuing the line, shown after the cursor
+    pub fn suggestion(&self) -> Option<String> {
+        if self.cursor != self.buffer.len() || self.menu.is_some() {
+            return None;
+        }
+        let cwd = std::env::current_dir().ok()?;
+        // Only the rest of the current line is shown
+        history::suggest(&self.buffer, &cwd).map(|s| match s.find('\n') {
+            Some(i) => s[..i].to_string(),
+            None => s,
+        })
+    }
+
+    pub fn accept_suggestion(&mut self) {
+        if let Some(s) = self.suggestion() {
+            self.buffer.push_str(&s);
+            self.cursor = self.buffer.len();
+        }
+    }
+
+    /// Accept the autosuggestion up to the end of its next word
+    pub fn accept_suggestion_word(&mut self) {
+        let s = match self.suggestion() {
+            Some(s) => s,
+            None => return,
+        };
+        let leading = s.len() - s.trim_start().len();
+        let end = s[leading..]
+            .find(char::is_whitespace)
+            .map_or(s.len(), |i| leading + i);
+        self.buffer.push_str(&s[..end]);
+        self.cursor = self.buffer.len();
+    }
+
+    pub fn history_previous(&mut self) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(i) => i - 1,
+            None => {
+                if history::len() == 0 {
+                    return;
+                }
+                self.saved = self.buffer.clone();
+                history::len() - 1
+            }
+        };
+        if let Some(command) = history::get(index) {
+            self.history_index = Some(index);
+            self.set_buffer(command);
+        }
+    }
+
+    /// Show the previous history entry containing the text typed before browsing
+    pub fn history_search_previous(&mut self) {
+        let before = match self.history_index {
+            Some(i) => i,
+            None => {
+                self.saved = self.buffer.clone();
+                history::len()
+            }
+        };
+        if let Some((index, command)) = history::search_backward(&self.saved, before) {
+            self.history_index = Some(index);
+            self.set_buffer(command);
+        }
+    }
+
+    /// Show the next history entry containing the text typed before browsing,
+    /// or that text itself after the last one
+    pub fn history_search_next(&mut self) {
+        let after = match self.history_index {
+            Some(i) => i,
+            None => return,
+        };
+        match history::search_forward(&self.saved, after) {
+            Some((index, command)) => {
+                self.history_index = Some(index);
+                self.set_buffer(command);
+            }
+            None => {
+                self.history_index = None;
+                let saved = std::mem::take(&mut self.saved);
+                self.set_buffer(saved);
+            }
+        }
+    }
+
+    pub fn history_next(&mut self) {
+        let index = match self.history_index {
+            Some(i) => i + 1,
+            None => return,
+        };
+        match history::get(index) {
+            Some(command) => {
+                self.history_index = Some(index);
+                self.set_buffer(command);
+            }
+            None => {
+                self.history_index = None;
+                let saved = std::mem::take(&mut self.saved);
+                self.set_buffer(saved);
+            }
+        }
+    }
+
+    /// Complete the word before the cursor
+    pub fn complete(&mut self) {
+        let completion = completion::complete(&self.buffer[..self.cursor]);
+        let start = completion.start;
+        match completion.candidates.len() {
+            0 => {}
+            1 => {
+                let mut replacement = completion.candidates[0].replacement.clone();
+                if !replacement.ends_with('/') {
+                    replacement.push(' ');
+                }
+                self.buffer.replace_range(start..self.cursor, &replacement);
+                self.cursor = start + replacement.len();
+            }
+            _ => {
+                // Insert what all candidates have in common, then show the rest
+                let prefix = completion::common_prefix(&completion.candidates);
+                if prefix.len() > self.cursor - start {
+                    self.buffer.replace_range(start..self.cursor, &prefix);
+                    self.cursor = start + prefix.len();
+                }
+                self.menu = Some(Menu::new(completion, self.buffer.clone(), self.cursor));
+            }
+        }
+    }
+
+    /// Handle a key while the completion menu is open.
+    /// Returns false if the key closed the menu and should be handled as usual.
+    pub fn menu_key(&mut self, code: KeyCode) -> bool {
+        let m = match self.menu.as_mut() {
+            Some(m) => m,
+            None => return false,
+        };
+        match code {
+            KeyCode::Tab | KeyCode::Down => m.next(),
+            KeyCode::BackTab | KeyCode::Up => m.previous(),
+            KeyCode::Left => m.left(),
+            KeyCode::Right => m.right(),
+            KeyCode::Enter => {
+                self.menu = None;
+                return true;
+            }
+            KeyCode::Esc => {
+                let original = m.original.clone();
+                let cursor = m.cursor;
+                self.buffer = original;
+                self.cursor = cursor;
+                self.menu = None;
+                return true;
+            }
+            _ => {
+                self.menu = None;
+                return false;
+            }
+        }
+        // Undoing brings back the line as it was before choosing from the menu
+        let (original, original_cursor) = (m.original.clone(), m.cursor);
+        let (buffer, cursor) = m.apply();
+        if !vi::enabled() {
+            self.push_undo(original, original_cursor);
+        }
+        self.buffer = buffer;
+        self.cursor = cursor;
+        true
+    }
+
+    /// Remove the text between two byte offsets and put the cursor at the start
+    pub fn delete_range(&mut self, start: usize, end: usize) {
+        self.buffer.replace_range(start..end, "");
+        self.cursor = start;
+    }
+
+    /// Delete text into the kill ring.
+    /// Kills made one after another end up in the same entry.
+    fn kill_range(&mut self, start: usize, end: usize) {
+        let backward = end == self.cursor && start < end;
+        kill_ring::kill(
+            &self.buffer[start..end],
+            self.last == Last::Killed,
+            backward,
+        );
+        self.delete_range(start, end);
+    }
+
+    /// Kill from the cursor to the end of the line,
+    /// or the line break when already there
+    pub fn kill_line(&mut self) {
+        let end = self.line_end();
+        if end == self.cursor && end < self.buffer.len() {
+            self.kill_range(self.cursor, end + 1);
+        } else {
+            self.kill_range(self.cursor, end);
+        }
+    }
+
+    /// Kill from the start of the line to the cursor
+    pub fn unix_line_discard(&mut self) {
+        self.kill_range(self.line_start(), self.cursor);
+    }
+
+    pub fn kill_word(&mut self) {
+        let end = forward_word_end(&self.buffer, self.cursor);
+        self.kill_range(self.cursor, end);
+    }
+
+    pub fn backward_kill_word(&mut self) {
+        let start = previous_word_start(&self.buffer, self.cursor);
+        self.kill_range(start, self.cursor);
+    }
+
+    /// Insert the most recent kill. Returns false if nothing was killed yet.
+    pub fn yank(&mut self) -> bool {
+        match kill_ring::yank() {
+            Some(text) => {
+                self.insert_yanked(&text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace the text just yanked with the previous kill.
+    /// Returns false unless the last key yanked.
+    pub fn yank_pop(&mut self) -> bool {
+        if self.last != Last::Yanked {
+            return false;
+        }
+        match kill_ring::rotate() {
+            Some(text) => {
+                let (start, end) = self.yanked;
+                self.delete_range(start, end);
+                self.insert_yanked(&text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert_yanked(&mut self, text: &str) {
+        self.buffer.insert_str(self.cursor, text);
+        self.yanked = (self.cursor, self.cursor + text.len());
+        self.cursor += text.len();
+    }
+
+    /// Move past the end of the next word,
+    /// or accept a word of the autosuggestion at the end of the line
+    pub fn forward_word(&mut self) {
+        if self.cursor == self.buffer.len() {
+            self.accept_suggestion_word();
+        } else {
+            self.cursor = forward_word_end(&self.buffer, self.cursor);
+        }
+    }
+
+    pub fn backward_word(&mut self) {
+        self.cursor = previous_word_start(&self.buffer, self.cursor);
+    }
+
+    /// Run an editing action.
+    /// Actions that affect the shell itself, like accept-line, are left to the caller.
+    pub fn perform(&mut self, action: Action) {
+        let (buffer, cursor) = (self.buffer.clone(), self.cursor);
+        let mut last = Last::Other;
+        match action {
+            Action::AcceptSuggestion => self.accept_suggestion(),
+            Action::AcceptSuggestionWord => self.accept_suggestion_word(),
+            Action::BackwardChar => self.move_left(),
+            Action::BackwardDeleteChar => self.backspace(),
+            Action::BackwardKillWord => {
+                self.backward_kill_word();
+                last = Last::Killed;
+            }
+            Action::BackwardWord => self.backward_word(),
+            Action::BeginningOfLine => self.home(),
+            Action::Complete => self.complete(),
+            Action::DeleteChar => self.delete(),
+            Action::EndOfLine => self.end(),
+            Action::ForwardChar => self.move_right(),
+            Action::ForwardWord => self.forward_word(),
+            Action::InsertNewline => self.insert('\n'),
+            Action::KillLine => {
+                self.kill_line();
+                last = Last::Killed;
+            }
+            Action::KillWord => {
+                self.kill_word();
+                last = Last::Killed;
+            }
+            Action::NextHistory => {
+                if !self.move_down() {
+                    self.history_next();
+                }
+            }
+            Action::PreviousHistory => {
+                if !self.move_up() {
+                    self.history_previous();
+                }
+            }
+            Action::Redo => {
+                self.redo();
+                self.last = Last::Other;
+                return;
+            }
+            Action::Undo => {
+                self.undo();
+                self.last = Last::Other;
+                return;
+            }
+            Action::UnixLineDiscard => {
+                self.unix_line_discard();
+                last = Last::Killed;
+            }
+            Action::Yank => {
+                if self.yank() {
+                    last = Last::Yanked;
+                }
+            }
+            Action::YankPop => {
+                if self.yank_pop() {
+                    last = Last::Yanked;
+                }
+            }
+            Action::AcceptLine | Action::ClearScreen | Action::EditCommandLine | Action::Exit => {}
+        }
+        self.last = last;
+
+        // vi mode records its own undo states, a whole command at a time
+        if !vi::enabled() && self.buffer != buffer {
+            self.push_undo(buffer, cursor);
+        }
+    }
+
+    /// Handle a mouse event when mouse capture is on.
+    /// Returns true if the line needs to be redrawn.
+    pub fn mouse(&mut self, event: MouseEvent) -> bool {
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(m) = self.menu.as_mut() {
+                    if let Some(i) = m.candidate_at(event.column, event.row) {
+                        // Clicking the selected candidate again accepts it
+                        if m.selected == Some(i) {
+                            self.menu = None;
+                            return true;
+                        }
+                        m.selected = Some(i);
+                        let (original, original_cursor) = (m.original.clone(), m.cursor);
+                        let (buffer, cursor) = m.apply();
+                        if !vi::enabled() {
+                            self.push_undo(original, original_cursor);
+                        }
+                        self.buffer = buffer;
+                        self.cursor = cursor;
+                        return true;
+                    }
+                }
+                match self.offset_at(event.column, event.row) {
+                    Some(offset) => {
+                        self.menu = None;
+                        self.cursor = offset;
+                        true
+                    }
+                    None => false,
+                }
+            }
+            MouseEventKind::ScrollUp => {
+                match self.menu.as_mut() {
+                    Some(m) => {
+                        m.previous();
+                        (self.buffer, self.cursor) = m.apply();
+                    }
+                    None => self.history_search_previous(),
+                }
+                true
+            }
+            MouseEventKind::ScrollDown => {
+                match self.menu.as_mut() {
+                    Some(m) => {
+                        m.next();
+                        (self.buffer, self.cursor) = m.apply();
+                    }
+                    None => self.history_search_next(),
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Byte offset in the input of the grapheme shown at a screen position.
+    /// Clicking past the end of a row goes to the end of that row.
+    fn offset_at(&self, column: u16, row: u16) -> Option<usize> {
+        let width = crossterm::terminal::size().map_or(80, |s| s.0.max(1) as usize);
+        let target = (row as usize).checked_sub(self.row as usize + self.prompt_rows)?;
+        let column = column as usize;
+
+        let mut offset = None;
+        let (mut r, mut c) = (0, self.prompt_width as usize);
+        for (i, g) in self.buffer.grapheme_indices(true) {
+            if r == target && (c <= column || offset.is_none()) {
+                offset = Some(i);
+            }
+            let (rows, next) = advance(g, width, c, CONTINUATION_PROMPT.len());
+            r += rows;
+            c = next;
+            if r > target {
+                return offset;
+            }
+        }
+        if r == target {
+            offset = Some(self.buffer.len());
+        }
+        offset
+    }
+
+    /// Draw the right prompt if the first row of the input leaves room for it
+    fn render_right_prompt(&self, printed: &str, width: usize) {
+        if self.right_prompt.is_empty() || (self.transient && self.hide_suggestion) {
+            return;
+        }
+        let right_width = grapheme::width(&grapheme::strip_escapes(&self.right_prompt));
+        let first_line = printed.split('\n').next().unwrap_or("");
+        let (rows, column) = self.locate(first_line, width);
+        if rows > 0 || column + 1 + right_width > width {
+            return;
+        }
+        let row = self.row + self.prompt_rows as u16;
+        queue!(
+            stdout(),
+            MoveTo((width - right_width) as u16, row),
+            Print(&self.right_prompt)
+        )
+        .unwrap();
+    }
+
+    /// Redraw the line without the autosuggestion and return it
+    pub fn finish(&mut self) -> String {
+        self.menu = None;
+        self.hide_suggestion = true;
+        self.cursor = self.buffer.len();
+        self.render();
+        queue!(stdout(), SetCursorStyle::DefaultUserShape).unwrap();
+        self.buffer.clone()
+    }
+
+    /// Row (relative to the first input row) and column reached
+    /// after printing `text` from the start of the input
+    fn locate(&self, text: &str, width: usize) -> (usize, usize) {
+        advance(
+            text,
+            width,
+            self.prompt_width as usize,
+            CONTINUATION_PROMPT.len(),
+        )
+    }
+
+    /// The prompt as printed, with the vi mode in front of its last line
+    fn full_prompt(&self) -> String {
+        // Submitted lines keep just the minimal prompt in the scrollback
+        if self.transient && self.hide_suggestion {
+            return TRANSIENT_PROMPT.to_string();
+        }
+        let (head, last) = match self.prompt.rfind('\n') {
+            Some(i) => self.prompt.split_at(i + 1),
+            None => ("", self.prompt.as_str()),
+        };
+        let indicator = match (vi::enabled(), self.vi.mode) {
+            (false, _) => String::new(),
+            (true, Mode::Insert) => "[I] ".with(self.scheme.suggestion().color).to_string(),
+            (true, Mode::Normal) => "[N] ".with(self.scheme.suggestion().color).to_string(),
+        };
+        format!("{}{}{}", head, indicator, last)
+    }
+
+    /// Rows above the input and column where it starts,
+    /// once the prompt is printed at the terminal's width
+    fn prompt_layout(&self, width: usize) -> (usize, u16) {
+        let visible = grapheme::strip_escapes(&self.full_prompt());
+        let (rows, column) = advance(&visible, width, 0, 0);
+        (rows, column as u16)
+    }
+
+    /// The terminal has been resized and may have rewrapped what is on screen.
+    /// Find the top of the prompt again from the cursor and draw everything anew.
+    pub fn resize(&mut self) {
+        let width = crossterm::terminal::size().map_or(80, |s| s.0.max(1) as usize);
+        (self.prompt_rows, self.prompt_width) = self.prompt_layout(width);
+        let (row, _) = self.locate(&self.buffer[..self.cursor], width);
+        let cursor_row = crossterm::cursor::position().map_or(self.row, |p| p.1);
+        self.row = cursor_row.saturating_sub((self.prompt_rows + row) as u16);
+        self.render();
+    }
+
+    /// Redraw the input, the autosuggestion and the completion menu,
+    /// then place the cursor
+    pub fn render(&mut self) {
+        let width = crossterm::terminal::size().map_or(80, |s| s.0.max(1) as usize);
+        queue!(
+            stdout(),
+            MoveTo(0, self.row),
+            Clear(ClearType::FromCursorDown)
+        )
+        .unwrap();
+
+        // In vi mode the cursor shape shows the mode too
+        if vi::enabled() {
+            let shape = match self.vi.mode {
+                Mode::Insert => SetCursorStyle::SteadyBar,
+                Mode::Normal => SetCursorStyle::SteadyBlock,
+            };
+            queue!(stdout(), shape).unwrap();
+        }
+        queue!(stdout(), Print(self.full_prompt().replace('\n', "\r\n"))).unwrap();
+        (self.prompt_rows, self.prompt_width) = self.prompt_layout(width);
+
+        // While searching the history, the search pattern takes the place of the input
+        if let Some(pattern) = &self.vi.search {
+            let line = format!("/{}", pattern);
+            queue!(stdout(), Print(&line)).unwrap();
+            stdout().flush().unwrap();
+            return;
+        }
+
+        highlight(
+            &self.buffer,
+            self.scheme,
+            &format!("\r\n{}", CONTINUATION_PROMPT),
+        );
+        let mut printed = self.buffer.clone();
+        if !self.hide_suggestion {
+            if let Some(s) = self.suggestion() {
+                queue!(
+                    stdout(),
+                    self.scheme.suggestion(),
+                    Print(&s),
+                    SetAttribute(Attribute::Reset),
+                    ResetColor
+                )
+                .unwrap();
+                printed.push_str(&s);
+            }
+        }
+        if let Some(m) = self.menu.as_mut() {
+            m.render();
+        }
+
+        // Printing may have scrolled the screen, so work out where the input starts now.
+        // Text that exactly fills a row leaves the cursor on that row's last column.
+        stdout().flush().unwrap();
+        let end_row = crossterm::cursor::position().map_or(self.row, |p| p.1);
+        let (mut rows, column) = self.locate(&printed, width);
+        if column == 0 && rows > 0 && !printed.ends_with('\n') {
+            rows -= 1;
+        }
+        self.row = end_row.saturating_sub((self.prompt_rows + rows) as u16);
+        if let Some(m) = self.menu.as_mut() {
+            m.top = end_row + 1;
+        }
+        self.render_right_prompt(&printed, width);
+
+        let (row, column) = self.locate(&self.buffer[..self.cursor], width);
+        let row = self.row + (self.prompt_rows + row) as u16;
+        queue!(stdout(), MoveTo(column as u16, row)).unwrap();
+        stdout().flush().unwrap();
+    }
+}
+
+/// Row and column reached after printing `text` from `column` of the first row.
+/// Lines after a line break start at `newline_column`.
+fn advance(text: &str, width: usize, column: usize, newline_column: usize) -> (usize, usize) {
+    let mut row = 0;
+    let mut column = column;
+    for g in text.graphemes(true) {
+        if g == "\n" {
+            row += 1;
+            column = newline_column;
+            continue;
+        }
+        let w = grapheme::grapheme_width(g);
+        // A wide character that doesn't fit at the end of a row goes to the next one
+        if column + w > width {
+            row += 1;
+            column = 0;
+        }
+        column += w;
+        if column >= width {
+            row += 1;
+            column -= width;
+        }
+    }
+    (row, column)
+}
+
+/// Let the user edit `text` in $VISUAL or $EDITOR and return the result.
+/// Raw mode is turned off while the editor runs.
+pub fn edit_externally(text: &str) -> std::io::Result<String> {
+    let program = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from(if cfg!(windows) { "notepad" } else { "vi" }));
+    // The variable may carry arguments, like "code --wait"
+    let mut words = program.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+
+    let (path, mut file) = create_temp_file()?;
+    file.write_all(format!("{}\n", text).as_bytes())?;
+    drop(file);
+
+    disable_raw_mode()?;
+    let status = crate::without_mouse(|| {
+        std::process::Command::new(program)
+            .args(words)
+            .arg(&path)
+            .status()
+    });
+    enable_raw_mode()?;
+
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    if !status?.success() {
+        return Err(std::io::Error::other(format!(
+            "{} exited with an error",
+            program
+        )));
+    }
+    // Editors end the file with a line break that isn't part of the command
+    let edited = edited?.replace("\r\n", "\n");
+    Ok(edited.trim_end_matches('\n').to_string())
+}
+
+/// A new file in the temp directory that nobody else can have made, under a random name
+/// so that another user can't plant a symlink there in advance
+fn create_temp_file() -> std::io::Result<(std::path::PathBuf, std::fs::File)> {
+    use std::hash::{BuildHasher, RandomState};
+    loop {
+        let suffix = RandomState::new().hash_one(std::process::id());
+        let path = std::env::temp_dir().join(format!("xcys-{:016x}.sh", suffix));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// What the previous key did to the line
+#[derive(Clone, Copy, PartialEq)]
+enum Last {
+    Other,
+    Typed,
+    Killed,
+    Yanked,
+}
+
+fn class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// Byte offset of the start of the word after `pos`
+pub fn next_word_start(s: &str, pos: usize) -> usize {
+    let mut chars = s[pos..].char_indices().peekable();
+    if let Some(&(_, c)) = chars.peek() {
+        let k = class(c);
+        if k != 0 {
+            while chars.next_if(|&(_, c)| class(c) == k).is_some() {}
+        }
+    }
+    while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+    chars.peek().map_or(s.len(), |&(i, _)| pos + i)
+}
+
+/// Byte offset of the start of the word before `pos`
+pub fn previous_word_start(s: &str, pos: usize) -> usize {
+    let mut chars = s[..pos].char_indices().rev().peekable();
+    while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+    let mut start = chars.peek().map_or(0, |&(i, _)| i);
+    if let Some(&(_, c)) = chars.peek() {
+        let k = class(c);
+        while let Some((i, _)) = chars.next_if(|&(_, c)| class(c) == k) {
+            start = i;
+        }
+    }
+    start
+}
+
+/// Byte offset of the last character of the word at or after the one following `pos`
+pub fn word_end(s: &str, pos: usize) -> usize {
+    let mut chars = s[pos..].char_indices().skip(1).peekable();
+    while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+    let (mut end, k) = match chars.next() {
+        Some((i, c)) => (i, class(c)),
+        None => return s.char_indices().last().map_or(0, |(i, _)| i),
+    };
+    while let Some((i, _)) = chars.next_if(|&(_, c)| class(c) == k) {
+        end = i;
+    }
+    pos + end
+}
+
+/// Byte offset just past the end of the word at or after `pos`, as emacs moves
+pub fn forward_word_end(s: &str, pos: usize) -> usize {
+    let mut chars = s[pos..].char_indices().peekable();
+    while chars.next_if(|&(_, c)| class(c) != 1).is_some() {}
+    while chars.next_if(|&(_, c)| class(c) == 1).is_some() {}
+    chars.peek().map_or(s.len(), |&(i, _)| pos + i)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn word_motions() {
+        let s = "git commit --amend";
+        assert_eq!(next_word_start(s, 0), 4);
+        assert_eq!(next_word_start(s, 11), 13);
+        assert_eq!(previous_word_start(s, 11), 4);
+        assert_eq!(previous_word_start(s, 4), 0);
+        assert_eq!(word_end(s, 0), 2);
+        assert_eq!(word_end(s, 2), 9);
+        assert_eq!(forward_word_end(s, 0), 3);
+        assert_eq!(forward_word_end(s, 10), 18);
+    }
+
+    #[test]
+    fn layout_wraps_wide_characters() {
+        assert_eq!(advance("abcd", 4, 2, 2), (1, 2));
+        assert_eq!(advance("a日本", 4, 0, 2), (1, 2));
+        assert_eq!(advance("ab\ncd", 80, 5, 2), (1, 4));
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut editor = Editor::new(ColorScheme::default(), "$ ");
+        for c in "git commit".chars() {
+            editor.type_char(c);
+        }
+        editor.perform(Action::BackwardKillWord);
+        assert_eq!(editor.buffer, "git ");
+        editor.perform(Action::Undo);
+        assert_eq!(editor.buffer, "git commit");
+        editor.perform(Action::Undo);
+        assert_eq!(editor.buffer, "git");
+        editor.perform(Action::Redo);
+        assert_eq!(editor.buffer, "git commit");
+    }
+}
@@ -1,15 +1,22 @@
 use std::error;
 use std::fmt;
+use std::path::PathBuf;
 
 #[derive(Clone)]
 pub enum ErrorKind {
     Null,
+    UnterminatedQuote,
+    Unsupported,
 }
 
 impl ErrorKind {
     pub fn description(&self) -> &'static str {
         match self {
             ErrorKind::Null => "No command has been entered.",
+            ErrorKind::UnterminatedQuote => "A quote is never closed.",
+            ErrorKind::Unsupported => {
+                "Pipelines, redirections and compound commands are not supported."
+            }
         }
     }
 }
@@ -19,6 +26,7 @@ pub struct CommandParseError {
 }
 
 impl CommandParseError {
+    #[allow(dead_code)]
     pub fn new<E>(kind: ErrorKind, error: E) -> Self
     where
         E: Into<Box<dyn error::Error + Send + Sync>>,
@@ -33,10 +41,18 @@ impl CommandParseError {
             _error: _CommandParseError::Simple(kind),
         }
     }
+
+    pub fn kind(&self) -> &ErrorKind {
+        match &self._error {
+            _CommandParseError::Simple(kind) => kind,
+            _CommandParseError::Custom((kind, _)) => kind,
+        }
+    }
 }
 
 enum _CommandParseError {
     Simple(ErrorKind),
+    #[allow(dead_code)]
     Custom((ErrorKind, Box<dyn error::Error + Send + Sync>)),
 }
 
@@ -54,3 +70,29 @@ impl fmt::Debug for CommandParseError {
         <Self as fmt::Display>::fmt(self, f)
     }
 }
+
+/// A problem found in a config file
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigError {
+    pub file: PathBuf,
+    /// Line and column, counted from 1
+    pub position: Option<(usize, usize)>,
+    /// Key the problem is about, like "Editor.mode"
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some((line, column)) = self.position {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, ": {}", key)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl error::Error for ConfigError {}
@@ -0,0 +1,237 @@
+use git2::{BranchType, Repository, RepositoryState, Status, StatusOptions};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// What the prompt shows about a git repository
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct GitStatus {
+    /// Branch name, or the short commit id when HEAD is detached
+    pub branch: String,
+    pub detached: bool,
+    pub staged: usize,
+    /// Tracked files changed in the working tree
+    pub dirty: usize,
+    pub untracked: usize,
+    pub conflicted: usize,
+    /// Commits the branch has that its upstream doesn't, and the other way round
+    pub ahead: usize,
+    pub behind: usize,
+    pub stashes: usize,
+    /// Operation in progress, like "rebase" or "merge"
+    pub state: Option<&'static str>,
+}
+
+/// Files git rewrites when staging, committing, switching branches, fetching or stashing
+const STAMPS: [&str; 5] = ["index", "HEAD", "FETCH_HEAD", "refs/stash", "packed-refs"];
+
+struct Cached {
+    git_dir: PathBuf,
+    stamps: Vec<Option<SystemTime>>,
+    status: GitStatus,
+}
+
+/// Status of the last repository looked at, with the mtimes of its `STAMPS` files.
+/// Editing or creating a file in the working tree touches none of them,
+/// so the cache is also dropped by `invalidate` after each command.
+static CACHE: Mutex<Option<Cached>> = Mutex::new(None);
+
+/// Set when the working tree may have changed since the cached status was read
+static STALE: AtomicBool = AtomicBool::new(false);
+
+/// Read the status again next time, as after a command that may have changed files
+pub fn invalidate() {
+    STALE.store(true, Ordering::SeqCst);
+}
+
+/// Status of the repository containing `dir`, if any
+pub fn status(dir: &Path) -> Option<GitStatus> {
+    let mut repo = Repository::discover(dir).ok()?;
+    let git_dir = repo.path().to_path_buf();
+    let stamps: Vec<Option<SystemTime>> = STAMPS
+        .iter()
+        .map(|name| {
+            std::fs::metadata(git_dir.join(name))
+                .and_then(|m| m.modified())
+                .ok()
+        })
+        .collect();
+
+    let mut cache = CACHE.lock().unwrap();
+    let stale = STALE.swap(false, Ordering::SeqCst);
+    if let Some(c) = cache.as_ref() {
+        if !stale && c.git_dir == git_dir && c.stamps == stamps {
+            return Some(c.status.clone());
+        }
+    }
+
+    let status = read_status(&mut repo);
+    *cache = Some(Cached {
+        git_dir,
+        stamps,
+        status: status.clone(),
+    });
+    Some(status)
+}
+
+/// Branch of the repository containing `dir`, without the slower counts of `status`
+pub fn branch(dir: &Path) -> Option<String> {
+    let repo = Repository::discover(dir).ok()?;
+    let mut status = GitStatus::default();
+    read_branch(&repo, &mut status);
+    Some(status.branch)
+}
+
+fn read_status(repo: &mut Repository) -> GitStatus {
+    let mut status = GitStatus::default();
+    read_branch(repo, &mut status);
+
+    // Compare with the upstream branch, if one is set
+    if let (false, Ok(head)) = (status.detached, repo.head()) {
+        let upstream = repo
+            .find_branch(&status.branch, BranchType::Local)
+            .and_then(|b| b.upstream());
+        if let (Some(local), Ok(upstream)) = (head.target(), upstream) {
+            if let Some(remote) = upstream.get().target() {
+                if let Ok((ahead, behind)) = repo.graph_ahead_behind(local, remote) {
+                    status.ahead = ahead;
+                    status.behind = behind;
+                }
+            }
+        }
+    }
+
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).exclude_submodules(true);
+    if let Ok(statuses) = repo.statuses(Some(&mut options)) {
+        for entry in statuses.iter() {
+            let s = entry.status();
+            if s.is_conflicted() {
+                status.conflicted += 1;
+                continue;
+            }
+            if s.intersects(
+                Status::INDEX_NEW
+                    | Status::INDEX_MODIFIED
+                    | Status::INDEX_DELETED
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE,
+            ) {
+                status.staged += 1;
+            }
+            if s.intersects(
+                Status::WT_MODIFIED
+                    | Status::WT_DELETED
+                    | Status::WT_RENAMED
+                    | Status::WT_TYPECHANGE,
+            ) {
+                status.dirty += 1;
+            }
+            if s.is_wt_new() {
+                status.untracked += 1;
+            }
+        }
+    }
+
+    let _ = repo.stash_foreach(|_, _, _| {
+        status.stashes += 1;
+        true
+    });
+
+    status.state = match repo.state() {
+        RepositoryState::Clean => None,
+        RepositoryState::Merge => Some("merge"),
+        RepositoryState::Revert | RepositoryState::RevertSequence => Some("revert"),
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => Some("cherry-pick"),
+        RepositoryState::Bisect => Some("bisect"),
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge => Some("rebase"),
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => Some("am"),
+    };
+    status
+}
+
+/// Branch name, or the short commit id when HEAD is detached
+fn read_branch(repo: &Repository, status: &mut GitStatus) {
+    status.detached = repo.head_detached().unwrap_or(false);
+    match repo.head() {
+        Ok(head) if status.detached => {
+            if let Some(id) = head.target() {
+                status.branch = id.to_string()[..7].to_string();
+            }
+        }
+        Ok(head) => status.branch = head.shorthand().unwrap_or("").to_string(),
+        // A new repository has no commit for HEAD to point at yet
+        Err(_) => {
+            if let Ok(head) = repo.find_reference("HEAD") {
+                if let Some(target) = head.symbolic_target() {
+                    status.branch = target.trim_start_matches("refs/heads/").to_string();
+                }
+            }
+        }
+    }
+}
+
+impl GitStatus {
+    /// Like "main|rebase ↑1 ↓2 +3 !1 ?4 =1 $2". Counts that are zero are left out.
+    pub fn summary(&self) -> String {
+        let mut summary = if self.detached {
+            format!("@{}", self.branch)
+        } else {
+            self.branch.clone()
+        };
+        if let Some(state) = self.state {
+            summary.push('|');
+            summary.push_str(state);
+        }
+        for (symbol, count) in [
+            ('↑', self.ahead),
+            ('↓', self.behind),
+            ('+', self.staged),
+            ('!', self.dirty),
+            ('?', self.untracked),
+            ('=', self.conflicted),
+            ('$', self.stashes),
+        ] {
+            if count > 0 {
+                summary.push_str(&format!(" {}{}", symbol, count));
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn status_of_new_repository() {
+        let dir = std::env::temp_dir().join(format!("xcys-git-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        std::fs::write(dir.join("b.txt"), "b").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+
+        let status = status(&dir).unwrap();
+        let branch = branch(&dir);
+        // A new file touches nothing git keeps, so it shows once the cache is dropped
+        std::fs::write(dir.join("c.txt"), "c").unwrap();
+        let cached = super::status(&dir).unwrap();
+        invalidate();
+        let refreshed = super::status(&dir).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(status.branch, "main");
+        assert_eq!((status.staged, status.untracked), (1, 1));
+        assert_eq!(status.summary(), "main +1 ?1");
+        assert_eq!(branch.as_deref(), Some("main"));
+        assert_eq!(cached, status);
+        assert_eq!(refreshed.untracked, 2);
+    }
+}
@@ -0,0 +1,102 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Byte offset of the end of the grapheme starting at `pos`
+pub fn next(s: &str, pos: usize) -> usize {
+    s[pos..]
+        .graphemes(true)
+        .next()
+        .map_or(pos, |g| pos + g.len())
+}
+
+/// Byte offset of the start of the grapheme ending at `pos`
+pub fn previous(s: &str, pos: usize) -> usize {
+    s[..pos]
+        .graphemes(true)
+        .next_back()
+        .map_or(pos, |g| pos - g.len())
+}
+
+/// Columns a terminal uses to show a single grapheme
+pub fn grapheme_width(g: &str) -> usize {
+    // Emoji presentation selectors and joined emoji still take two columns
+    if g.contains('\u{FE0F}') {
+        2
+    } else {
+        g.width().min(2)
+    }
+}
+
+/// Columns a terminal uses to show `s`
+pub fn width(s: &str) -> usize {
+    s.graphemes(true).map(grapheme_width).sum()
+}
+
+/// `s` without the escape sequences that style it
+pub fn strip_escapes(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Control sequences end with a byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // Operating system commands, like a window title, end with BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// Byte offset of the grapheme at display column `column` of `line`, or its end
+pub fn column_offset(line: &str, column: usize) -> usize {
+    let mut width = 0;
+    for (i, g) in line.grapheme_indices(true) {
+        if width >= column {
+            return i;
+        }
+        width += grapheme_width(g);
+    }
+    line.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn widths_and_offsets() {
+        let s = "a日本e\u{301}👍🏽";
+        assert_eq!(width(s), 8);
+        assert_eq!(next(s, 7), 10);
+        assert_eq!(previous(s, 10), 7);
+        assert_eq!(previous(s, s.len()), 10);
+        assert_eq!(column_offset(s, 3), 4);
+        assert_eq!(column_offset(s, 6), 10);
+        assert_eq!(width("❤\u{FE0F}"), 2);
+        assert_eq!(
+            strip_escapes("\x1b[38;5;2muser\x1b[0m \x1b]0;title\x07$"),
+            "user $"
+        );
+    }
+}
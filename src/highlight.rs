@@ -0,0 +1,143 @@
+use crate::color::{ColorScheme, Style};
+use crate::command::{find_in_path, is_builtin, is_executable};
+use crate::lexer::{tokenize, TokenKind};
+use crossterm::queue;
+use crossterm::style::*;
+use std::io::stdout;
+use std::path::{Path, PathBuf};
+
+/// Queue `input` colored according to its tokens.
+/// Every newline is printed as `newline`.
+pub fn highlight(input: &str, scheme: ColorScheme, newline: &str) {
+    let styles = styles(input, scheme);
+
+    let mut chars = input.char_indices().peekable();
+    while let Some((start, _)) = chars.next() {
+        let style = styles[start];
+        let mut end = input.len();
+        while let Some(&(i, _)) = chars.peek() {
+            if styles[i] != style {
+                end = i;
+                break;
+            }
+            chars.next();
+        }
+
+        for (i, line) in input[start..end].split('\n').enumerate() {
+            if i > 0 {
+                queue!(stdout(), Print(newline)).unwrap();
+            }
+            queue!(
+                stdout(),
+                style,
+                Print(line),
+                SetAttribute(Attribute::Reset),
+                ResetColor
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Style of every byte of `input`
+fn styles(input: &str, scheme: ColorScheme) -> Vec<Style> {
+    let mut styles = vec![scheme.sub_command(); input.len()];
+    // Words naming existing paths are underlined
+    let mut fill = |start: usize, end: usize, style: Style, underline: bool| {
+        let style = match underline {
+            true => style.with(Attribute::Underlined),
+            false => style,
+        };
+        for s in &mut styles[start..end] {
+            *s = style;
+        }
+    };
+
+    // The first word of the line and the first word after an operator are commands
+    let mut command_position = true;
+    for t in tokenize(input) {
+        match t.kind {
+            TokenKind::Operator => {
+                fill(t.start, t.end, scheme.operator(), false);
+                command_position = true;
+                continue;
+            }
+            TokenKind::Redirect => {
+                fill(t.start, t.end, scheme.redirection(), false);
+                continue;
+            }
+            TokenKind::Word => {}
+        }
+
+        let (style, underline) = if command_position {
+            let style = if !t.variables.is_empty() || resolves(&t.text) {
+                scheme.command()
+            } else {
+                scheme.error()
+            };
+            (style, false)
+        } else if t.text.starts_with('-') && t.quotes.is_empty() {
+            (scheme.flags(), false)
+        } else {
+            (scheme.sub_command(), path_exists(&t.text))
+        };
+        fill(t.start, t.end, style, underline);
+
+        for &(start, end) in &t.quotes {
+            fill(start, end, scheme.string(), underline);
+        }
+        for &(start, end) in &t.variables {
+            fill(start, end, scheme.variable(), underline);
+        }
+        if let Some(start) = t.unterminated {
+            fill(start, t.end, scheme.error(), false);
+        }
+
+        command_position = false;
+    }
+
+    styles
+}
+
+/// Whether `command` is a builtin or an executable
+fn resolves(command: &str) -> bool {
+    if is_builtin(command) {
+        return true;
+    }
+    if command.contains(['/', '\\']) {
+        return is_executable(&expand_home(command));
+    }
+    find_in_path(command).is_some()
+}
+
+fn path_exists(s: &str) -> bool {
+    !s.is_empty() && expand_home(s).exists()
+}
+
+fn expand_home(s: &str) -> PathBuf {
+    match (s.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) => {
+            let mut p = home.into_os_string();
+            p.push(rest);
+            PathBuf::from(p)
+        }
+        _ => Path::new(s).to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn styles_follow_tokens() {
+        let scheme = ColorScheme::default();
+        let input = "cd src | no-such-command-xcys \"x";
+        let styles = styles(input, scheme);
+        assert!(styles[0] == scheme.command());
+        assert!(styles[3].attributes.has(Attribute::Underlined));
+        assert!(styles[7] == scheme.operator());
+        assert!(styles[9] == scheme.error());
+        assert!(styles[input.len() - 1] == scheme.error());
+    }
+}
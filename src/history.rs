@@ -0,0 +1,177 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub struct Entry {
+    pub command: String,
+    /// Directory the command was run in
+    pub cwd: PathBuf,
+}
+
+static HISTORY: Mutex<Vec<Entry>> = Mutex::new(vec![]);
+
+fn history_file() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("xcys").join("history"))
+}
+
+// One entry per line: "<cwd>\t<command>", with backslashes,
+// tabs and newlines escaped.
+fn encode(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn decode(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => decoded.push('\t'),
+            Some('n') => decoded.push('\n'),
+            Some(c) => decoded.push(c),
+            None => decoded.push('\\'),
+        }
+    }
+    decoded
+}
+
+/// Read the history file. Missing or unreadable files leave the history empty.
+pub fn load() {
+    let contents = match history_file().map(std::fs::read_to_string) {
+        Some(Ok(c)) => c,
+        _ => return,
+    };
+
+    let mut history = HISTORY.lock().unwrap();
+    for line in contents.lines() {
+        if let Some((cwd, command)) = line.split_once('\t') {
+            history.push(Entry {
+                command: decode(command),
+                cwd: PathBuf::from(decode(cwd)),
+            });
+        }
+    }
+}
+
+/// Record a command and append it to the history file
+pub fn add(command: &str, cwd: &Path) {
+    if command.trim().is_empty() {
+        return;
+    }
+
+    let mut history = HISTORY.lock().unwrap();
+    // Don't record the same command twice in a row
+    if history
+        .last()
+        .is_some_and(|e| e.command == command && e.cwd == cwd)
+    {
+        return;
+    }
+    history.push(Entry {
+        command: command.to_string(),
+        cwd: cwd.to_path_buf(),
+    });
+
+    if let Some(path) = history_file() {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Ok(mut f) = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+        {
+            let _ = writeln!(f, "{}\t{}", encode(&cwd.to_string_lossy()), encode(command));
+        }
+    }
+}
+
+pub fn len() -> usize {
+    HISTORY.lock().unwrap().len()
+}
+
+pub fn get(index: usize) -> Option<String> {
+    HISTORY
+        .lock()
+        .unwrap()
+        .get(index)
+        .map(|e| e.command.clone())
+}
+
+/// The most recent entry before `before` containing `pattern`
+pub fn search_backward(pattern: &str, before: usize) -> Option<(usize, String)> {
+    let history = HISTORY.lock().unwrap();
+    history[..before.min(history.len())]
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, e)| e.command.contains(pattern))
+        .map(|(i, e)| (i, e.command.clone()))
+}
+
+/// The oldest entry after `after` containing `pattern`
+pub fn search_forward(pattern: &str, after: usize) -> Option<(usize, String)> {
+    let history = HISTORY.lock().unwrap();
+    history
+        .iter()
+        .enumerate()
+        .skip(after + 1)
+        .find(|(_, e)| e.command.contains(pattern))
+        .map(|(i, e)| (i, e.command.clone()))
+}
+
+/// The rest of the most recent command starting with `prefix`.
+/// Commands run in `cwd` are preferred over ones run elsewhere.
+pub fn suggest(prefix: &str, cwd: &Path) -> Option<String> {
+    if prefix.is_empty() {
+        return None;
+    }
+
+    let history = HISTORY.lock().unwrap();
+    let mut matches = history
+        .iter()
+        .rev()
+        .filter(|e| e.command.len() > prefix.len() && e.command.starts_with(prefix));
+    let first = matches.next()?;
+    let best = if first.cwd == cwd {
+        first
+    } else {
+        matches.find(|e| e.cwd == cwd).unwrap_or(first)
+    };
+    Some(best.command[prefix.len()..].to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suggest_prefers_cwd() {
+        let here = Path::new("/project");
+        let elsewhere = Path::new("/elsewhere");
+        {
+            let mut history = HISTORY.lock().unwrap();
+            for (command, cwd) in [("cargo build", here), ("cargo test", elsewhere)] {
+                history.push(Entry {
+                    command: command.to_string(),
+                    cwd: cwd.to_path_buf(),
+                });
+            }
+        }
+        assert_eq!(suggest("cargo ", here).as_deref(), Some("build"));
+        assert_eq!(suggest("cargo ", elsewhere).as_deref(), Some("test"));
+        assert_eq!(suggest("cargo b", elsewhere).as_deref(), Some("uild"));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let s = "echo \"a\tb\"\nls C:\\Users";
+        assert_eq!(decode(&encode(s)), s);
+        assert!(!encode(s).contains('\n'));
+    }
+}
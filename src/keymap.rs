@@ -0,0 +1,336 @@
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::sync::Mutex;
+
+/// A key with its modifiers, like Ctrl-X
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Chord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Chord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        // The case of a character already tells whether Shift was held,
+        // and Ctrl-C is the same key whatever the case
+        let code = match code {
+            KeyCode::Char(c) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                if modifiers.contains(KeyModifiers::CONTROL) {
+                    KeyCode::Char(c.to_ascii_lowercase())
+                } else {
+                    code
+                }
+            }
+            _ => code,
+        };
+        Self { code, modifiers }
+    }
+}
+
+/// Named editor actions keys can be bound to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    AcceptLine,
+    AcceptSuggestion,
+    AcceptSuggestionWord,
+    BackwardChar,
+    BackwardDeleteChar,
+    BackwardKillWord,
+    BackwardWord,
+    BeginningOfLine,
+    ClearScreen,
+    Complete,
+    DeleteChar,
+    EditCommandLine,
+    EndOfLine,
+    Exit,
+    ForwardChar,
+    ForwardWord,
+    InsertNewline,
+    KillLine,
+    KillWord,
+    NextHistory,
+    PreviousHistory,
+    Redo,
+    Undo,
+    UnixLineDiscard,
+    Yank,
+    YankPop,
+}
+
+const ACTION_NAMES: [(&str, Action); 26] = [
+    ("accept-line", Action::AcceptLine),
+    ("accept-suggestion", Action::AcceptSuggestion),
+    ("accept-suggestion-word", Action::AcceptSuggestionWord),
+    ("backward-char", Action::BackwardChar),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("backward-kill-word", Action::BackwardKillWord),
+    ("backward-word", Action::BackwardWord),
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("clear-screen", Action::ClearScreen),
+    ("complete", Action::Complete),
+    ("delete-char", Action::DeleteChar),
+    ("edit-command-line", Action::EditCommandLine),
+    ("end-of-line", Action::EndOfLine),
+    ("exit", Action::Exit),
+    ("forward-char", Action::ForwardChar),
+    ("forward-word", Action::ForwardWord),
+    ("insert-newline", Action::InsertNewline),
+    ("kill-line", Action::KillLine),
+    ("kill-word", Action::KillWord),
+    ("next-history", Action::NextHistory),
+    ("previous-history", Action::PreviousHistory),
+    ("redo", Action::Redo),
+    ("undo", Action::Undo),
+    ("unix-line-discard", Action::UnixLineDiscard),
+    ("yank", Action::Yank),
+    ("yank-pop", Action::YankPop),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTION_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, a)| *a)
+    }
+
+    pub fn name(&self) -> &'static str {
+        ACTION_NAMES.iter().find(|(_, a)| a == self).unwrap().0
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        ACTION_NAMES.iter().map(|(n, _)| *n)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Binding {
+    Action(Action),
+    /// A command run as if it had been typed
+    Command(String),
+}
+
+pub enum Lookup {
+    Exact(Binding),
+    /// The keys are the start of a longer sequence
+    Prefix,
+    None,
+}
+
+static KEYMAP: Mutex<Vec<(Vec<Chord>, Binding)>> = Mutex::new(vec![]);
+
+const DEFAULT_BINDINGS: [(&str, Action); 41] = [
+    ("enter", Action::AcceptLine),
+    ("tab", Action::Complete),
+    ("backspace", Action::BackwardDeleteChar),
+    ("delete", Action::DeleteChar),
+    ("left", Action::BackwardChar),
+    ("right", Action::ForwardChar),
+    ("up", Action::PreviousHistory),
+    ("down", Action::NextHistory),
+    ("home", Action::BeginningOfLine),
+    ("end", Action::EndOfLine),
+    ("ctrl-left", Action::BackwardWord),
+    ("ctrl-right", Action::ForwardWord),
+    ("alt-enter", Action::InsertNewline),
+    ("ctrl-a", Action::BeginningOfLine),
+    ("ctrl-b", Action::BackwardChar),
+    ("ctrl-c", Action::Exit),
+    ("ctrl-d", Action::DeleteChar),
+    ("ctrl-e", Action::EndOfLine),
+    ("ctrl-f", Action::AcceptSuggestion),
+    ("ctrl-h", Action::BackwardDeleteChar),
+    ("ctrl-k", Action::KillLine),
+    ("ctrl-l", Action::ClearScreen),
+    ("ctrl-n", Action::NextHistory),
+    ("ctrl-p", Action::PreviousHistory),
+    ("ctrl-u", Action::UnixLineDiscard),
+    ("ctrl-w", Action::BackwardKillWord),
+    ("alt-b", Action::BackwardWord),
+    ("alt-d", Action::KillWord),
+    ("alt-f", Action::ForwardWord),
+    ("alt-backspace", Action::BackwardKillWord),
+    ("ctrl-backspace", Action::BackwardKillWord),
+    ("ctrl-delete", Action::KillWord),
+    ("alt-left", Action::BackwardWord),
+    ("alt-right", Action::ForwardWord),
+    ("ctrl-x ctrl-e", Action::EditCommandLine),
+    ("ctrl-y", Action::Yank),
+    ("alt-y", Action::YankPop),
+    ("ctrl-_", Action::Undo),
+    // Terminals send Ctrl-_ as the same byte as Ctrl-7
+    ("ctrl-7", Action::Undo),
+    ("ctrl-z", Action::Undo),
+    ("alt-_", Action::Redo),
+];
+
+/// Replace every binding with the default ones
+pub fn load_defaults() {
+    KEYMAP.lock().unwrap().clear();
+    for (keys, action) in DEFAULT_BINDINGS {
+        bind(parse_keys(keys).unwrap(), Binding::Action(action));
+    }
+}
+
+/// Bind a key sequence, replacing any previous binding of the same keys
+pub fn bind(keys: Vec<Chord>, binding: Binding) {
+    let mut keymap = KEYMAP.lock().unwrap();
+    keymap.retain(|(k, _)| *k != keys);
+    keymap.push((keys, binding));
+}
+
+/// Returns false if the keys were not bound
+pub fn unbind(keys: &[Chord]) -> bool {
+    let mut keymap = KEYMAP.lock().unwrap();
+    let len = keymap.len();
+    keymap.retain(|(k, _)| k != keys);
+    keymap.len() != len
+}
+
+pub fn lookup(keys: &[Chord]) -> Lookup {
+    let keymap = KEYMAP.lock().unwrap();
+    if let Some((_, b)) = keymap.iter().find(|(k, _)| k == keys) {
+        return Lookup::Exact(b.clone());
+    }
+    if keymap
+        .iter()
+        .any(|(k, _)| k.len() > keys.len() && k.starts_with(keys))
+    {
+        return Lookup::Prefix;
+    }
+    Lookup::None
+}
+
+pub fn bindings() -> Vec<(Vec<Chord>, Binding)> {
+    KEYMAP.lock().unwrap().clone()
+}
+
+const KEY_NAMES: [(&str, KeyCode); 17] = [
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("esc", KeyCode::Esc),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("space", KeyCode::Char(' ')),
+    ("minus", KeyCode::Char('-')),
+];
+
+/// Parse a key sequence like "ctrl-x ctrl-e" or "alt-f"
+pub fn parse_keys(s: &str) -> Result<Vec<Chord>, String> {
+    let keys: Vec<Chord> = s
+        .split_whitespace()
+        .map(parse_chord)
+        .collect::<Result<_, _>>()?;
+    if keys.is_empty() {
+        return Err(String::from("no keys given"));
+    }
+    Ok(keys)
+}
+
+fn parse_chord(s: &str) -> Result<Chord, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = s;
+    loop {
+        let lower = rest.to_ascii_lowercase();
+        let (modifier, len) = if lower.starts_with("ctrl-") {
+            (KeyModifiers::CONTROL, 5)
+        } else if lower.starts_with("alt-") {
+            (KeyModifiers::ALT, 4)
+        } else if lower.starts_with("shift-") {
+            (KeyModifiers::SHIFT, 6)
+        } else {
+            break;
+        };
+        if rest.len() == len {
+            break;
+        }
+        modifiers |= modifier;
+        rest = &rest[len..];
+    }
+
+    let lower = rest.to_ascii_lowercase();
+    let code = if let Some((_, code)) = KEY_NAMES.iter().find(|(n, _)| *n == lower) {
+        *code
+    } else if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        KeyCode::F(n)
+    } else {
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => return Err(format!("unknown key {}", s)),
+        }
+    };
+    Ok(Chord::new(code, modifiers))
+}
+
+pub fn format_keys(keys: &[Chord]) -> String {
+    keys.iter().map(format_chord).collect::<Vec<_>>().join(" ")
+}
+
+fn format_chord(chord: &Chord) -> String {
+    let mut s = String::new();
+    if chord.modifiers.contains(KeyModifiers::CONTROL) {
+        s.push_str("ctrl-");
+    }
+    if chord.modifiers.contains(KeyModifiers::ALT) {
+        s.push_str("alt-");
+    }
+    if chord.modifiers.contains(KeyModifiers::SHIFT) {
+        s.push_str("shift-");
+    }
+    match KEY_NAMES.iter().find(|(_, c)| *c == chord.code) {
+        Some((name, _)) => s.push_str(name),
+        None => match chord.code {
+            KeyCode::Char(c) => s.push(c),
+            KeyCode::F(n) => s.push_str(&format!("f{}", n)),
+            code => s.push_str(&format!("{:?}", code).to_ascii_lowercase()),
+        },
+    }
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_format() {
+        let keys = parse_keys("ctrl-x ctrl-E").unwrap();
+        assert_eq!(
+            keys,
+            [
+                Chord::new(KeyCode::Char('x'), KeyModifiers::CONTROL),
+                Chord::new(KeyCode::Char('e'), KeyModifiers::CONTROL)
+            ]
+        );
+        assert_eq!(format_keys(&keys), "ctrl-x ctrl-e");
+        assert_eq!(
+            format_keys(&parse_keys("alt-- F5").unwrap()),
+            "alt-minus f5"
+        );
+        assert!(parse_keys("ctrl-nokey").is_err());
+    }
+
+    #[test]
+    fn sequences() {
+        let keys = parse_keys("ctrl-q ctrl-w").unwrap();
+        bind(keys.clone(), Binding::Command(String::from("ls")));
+        assert!(matches!(lookup(&keys[..1]), Lookup::Prefix));
+        assert!(matches!(lookup(&keys), Lookup::Exact(Binding::Command(_))));
+        assert!(unbind(&keys));
+        assert!(matches!(lookup(&keys[..1]), Lookup::None));
+    }
+}
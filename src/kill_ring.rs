@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+
+/// Killed text, most recent last. Shared by every prompt.
+static RING: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+const MAX_KILLS: usize = 32;
+
+/// Put killed text in the ring.
+/// Consecutive kills are joined into one entry, in the order the text appeared.
+pub fn kill(text: &str, join: bool, backward: bool) {
+    push(&mut RING.lock().unwrap(), text, join, backward);
+}
+
+fn push(ring: &mut Vec<String>, text: &str, join: bool, backward: bool) {
+    if text.is_empty() {
+        return;
+    }
+    match ring.last_mut() {
+        Some(last) if join => {
+            if backward {
+                last.insert_str(0, text);
+            } else {
+                last.push_str(text);
+            }
+        }
+        _ => {
+            ring.push(text.to_string());
+            if ring.len() > MAX_KILLS {
+                ring.remove(0);
+            }
+        }
+    }
+}
+
+/// The most recent kill
+pub fn yank() -> Option<String> {
+    RING.lock().unwrap().last().cloned()
+}
+
+/// Move the most recent kill to the back of the ring and return the next one
+pub fn rotate() -> Option<String> {
+    rotate_ring(&mut RING.lock().unwrap())
+}
+
+fn rotate_ring(ring: &mut Vec<String>) -> Option<String> {
+    if let Some(last) = ring.pop() {
+        ring.insert(0, last);
+    }
+    ring.last().cloned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn kills_join_and_rotate() {
+        // A ring of its own, as the shared one is used by the editor tests too
+        let mut ring = vec![];
+        push(&mut ring, "world", false, false);
+        push(&mut ring, "hello ", true, true);
+        push(&mut ring, "!", true, false);
+        assert_eq!(ring.last().map(String::as_str), Some("hello world!"));
+        push(&mut ring, "other", false, false);
+        assert_eq!(ring.last().map(String::as_str), Some("other"));
+        assert_eq!(rotate_ring(&mut ring).as_deref(), Some("hello world!"));
+    }
+}
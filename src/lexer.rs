@@ -0,0 +1,245 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    Word,
+    /// `|`, `||`, `&&`, `&`, `;` and newlines
+    Operator,
+    /// `>`, `>>`, `<`, `2>&1`, `&>` and so on
+    Redirect,
+}
+
+/// A single token of the command line.
+/// `text` is the token with quotes and escapes removed,
+/// `start` and `end` are byte offsets into the original input.
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    /// Quoted regions of a word, quotes included
+    pub quotes: Vec<(usize, usize)>,
+    /// `$NAME` references outside of single quotes
+    pub variables: Vec<(usize, usize)>,
+    /// Offset of a quote that is never closed
+    pub unterminated: Option<usize>,
+}
+
+impl Token {
+    fn new(kind: TokenKind, text: String, start: usize, end: usize) -> Self {
+        Self {
+            kind,
+            text,
+            start,
+            end,
+            quotes: vec![],
+            variables: vec![],
+            unterminated: None,
+        }
+    }
+}
+
+const REDIRECTS: [&str; 6] = ["&>>", "&>", ">>", ">&", ">", "<"];
+const OPERATORS: [&str; 5] = ["&&", "||", "|", "&", ";"];
+
+/// Length and kind of the operator or redirection at the start of `s`
+fn operator_at(s: &str) -> Option<(usize, TokenKind)> {
+    // File descriptor of a redirection, like the 2 in "2>"
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &s[digits..];
+
+    if let Some(r) = REDIRECTS.iter().find(|r| rest.starts_with(*r)) {
+        if digits == 0 || !r.starts_with('&') {
+            let mut len = digits + r.len();
+            if r.ends_with('&') {
+                // Target descriptor, like the 1 in "2>&1"
+                len += s[len..]
+                    .bytes()
+                    .take_while(|b| b.is_ascii_digit() || *b == b'-')
+                    .count();
+            }
+            return Some((len, TokenKind::Redirect));
+        }
+    }
+
+    if digits > 0 {
+        return None;
+    }
+    OPERATORS
+        .iter()
+        .find(|o| s.starts_with(*o))
+        .map(|o| (o.len(), TokenKind::Operator))
+}
+
+/// Length of the variable name following a `$` at the start of `s`
+fn variable_at(s: &str) -> usize {
+    let rest = &s[1..];
+    if let Some(braced) = rest.strip_prefix('{') {
+        return match braced.find('}') {
+            Some(i) => i + 3,
+            None => s.len(),
+        };
+    }
+    match rest.chars().next() {
+        Some('?' | '$' | '!' | '#' | '0'..='9') => 2,
+        Some(c) if c.is_alphabetic() || c == '_' => {
+            1 + rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len())
+        }
+        _ => 1,
+    }
+}
+
+pub fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            // A newline separates commands like ";"
+            if c == '\n' {
+                tokens.push(Token::new(
+                    TokenKind::Operator,
+                    String::from("\n"),
+                    start,
+                    start + 1,
+                ));
+            }
+            continue;
+        }
+
+        if let Some((len, kind)) = operator_at(&input[start..]) {
+            while chars.peek().is_some_and(|&(i, _)| i < start + len) {
+                chars.next();
+            }
+            let text = input[start..start + len].to_string();
+            tokens.push(Token::new(kind, text, start, start + len));
+            continue;
+        }
+
+        let mut token = Token::new(TokenKind::Word, String::new(), start, start);
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_whitespace() || (i > start && "|&;<>".contains(c)) {
+                break;
+            }
+            chars.next();
+            token.end = i + c.len_utf8();
+            match c {
+                '\\' => {
+                    // A backslash before a newline joins the lines
+                    if let Some((i, c)) = chars.next() {
+                        if c != '\n' {
+                            token.text.push(c);
+                        }
+                        token.end = i + c.len_utf8();
+                    }
+                }
+                '\'' => {
+                    // Everything up to the closing quote is taken literally
+                    token.unterminated = Some(i);
+                    for (j, c) in chars.by_ref() {
+                        token.end = j + c.len_utf8();
+                        if c == '\'' {
+                            token.unterminated = None;
+                            break;
+                        }
+                        token.text.push(c);
+                    }
+                    token.quotes.push((i, token.end));
+                }
+                '"' => {
+                    token.unterminated = Some(i);
+                    while let Some((j, c)) = chars.next() {
+                        token.end = j + c.len_utf8();
+                        match c {
+                            '"' => {
+                                token.unterminated = None;
+                                break;
+                            }
+                            '\\' => match chars.peek() {
+                                Some(&(j, n)) if matches!(n, '"' | '\\' | '$' | '`') => {
+                                    chars.next();
+                                    token.text.push(n);
+                                    token.end = j + n.len_utf8();
+                                }
+                                _ => token.text.push(c),
+                            },
+                            '$' => {
+                                let len = variable_at(&input[j..]);
+                                if len > 1 {
+                                    token.variables.push((j, j + len));
+                                }
+                                token.text.push(c);
+                            }
+                            _ => token.text.push(c),
+                        }
+                    }
+                    token.quotes.push((i, token.end));
+                }
+                '$' => {
+                    let len = variable_at(&input[i..]);
+                    if len > 1 {
+                        token.variables.push((i, i + len));
+                    }
+                    token.text.push(c);
+                }
+                _ => token.text.push(c),
+            }
+        }
+
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+const SPECIAL_CHARS: &str = "\\'\"$`&|;<>()*?[]#!{}";
+
+/// Escape characters that have a special meaning to the shell,
+/// so that `s` is read back as a single word.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_whitespace() || SPECIAL_CHARS.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokenize_quotes() {
+        let tokens = tokenize(r#"echo "hello world" 'a b' c\ d"#);
+        let words: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(words, ["echo", "hello world", "a b", "c d"]);
+        assert_eq!(tokens[1].start, 5);
+        assert_eq!(tokens[1].end, 18);
+    }
+
+    #[test]
+    fn tokenize_operators() {
+        let tokens = tokenize("ls -l|grep \"$HOME\" 2>&1 >out && echo 'a");
+        let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind).collect();
+        use TokenKind::*;
+        assert_eq!(
+            kinds,
+            [Word, Word, Operator, Word, Word, Redirect, Redirect, Word, Operator, Word, Word]
+        );
+        assert_eq!(tokens[5].text, "2>&1");
+        assert_eq!(tokens[4].variables, [(12, 17)]);
+        assert_eq!(tokens[10].unterminated, Some(37));
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let s = "my file (1).txt";
+        let tokens = tokenize(&escape(s));
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].text, s);
+    }
+}
@@ -1,25 +1,44 @@
-#![feature(file_set_times)]
-
 mod builtin;
 mod color;
 mod command;
+mod completion;
+mod editor;
 mod error;
+mod git;
+mod grapheme;
+mod highlight;
+mod history;
+mod keymap;
+mod kill_ring;
+mod lexer;
+mod prompt;
+mod segment;
+mod theme;
+mod title;
 mod toml;
+mod vi;
 
 use std::io::stdout;
 use std::io::Write;
-use std::path::Path;
-
-use crate::color::ColorScheme;
-use crate::command::{parse_command, BuiltinCommand, ParsedCommand};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::command::{is_incomplete, parse_command, BuiltinCommand, ParsedCommand};
+use crate::editor::{edit_externally, Editor};
+use crate::error::ErrorKind;
+use crate::keymap::{Action, Binding, Chord, Lookup};
 use crate::toml::Config;
-use crossterm::event::{read, KeyEventKind, KeyModifiers};
+use crossterm::event::{
+    poll, read, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste,
+    EnableMouseCapture, KeyEventKind, KeyModifiers,
+};
 use crossterm::style::*;
-use crossterm::terminal::*;
 use crossterm::{
+    cursor::MoveTo,
     event::{Event, KeyCode, KeyEvent},
     execute, queue,
-    terminal::{disable_raw_mode, enable_raw_mode},
+    terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
     Result,
 };
 use octocrab::models::repos::Tag;
@@ -30,50 +49,73 @@ pub enum CommandType {
     Builtin(BuiltinCommand),
 }
 
-fn shell_loop(scheme: ColorScheme) {
-    loop {
-        let path = to_suitable_style(std::env::current_dir().unwrap().to_str().unwrap());
-        queue!(
-            stdout(),
-            crossterm::terminal::SetTitle(&path),
-            SetForegroundColor(Color::Green),
-            Print(whoami::username()),
-            Print("@"),
-            Print(whoami::devicename()),
-            SetForegroundColor(Color::Yellow),
-            Print(" "),
-            Print(path),
-            ResetColor
-        )
-        .unwrap();
-
-        if unsafe { GIT_ENABLED } {
-            match get_git_branch_name() {
-                Ok(b) => {
-                    queue!(
-                        stdout(),
-                        Print(" "),
-                        SetForegroundColor(Color::Cyan),
-                        Print("("),
-                        Print(to_suitable_style(
-                            std::str::from_utf8(b.as_slice()).unwrap()
-                        )),
-                        Print(")"),
-                        ResetColor
-                    )
-                    .unwrap();
-                }
-                Err(_) => {}
-            }
+/// `config_path` is the file given with `--config`, read again by `reload`
+fn shell_loop(mut config: Config, config_path: Option<PathBuf>) {
+    // Text to edit again at the next prompt
+    let mut restore: Option<String> = None;
+    let mut context = prompt::Context {
+        min_duration: config.duration_threshold(),
+        ..Default::default()
+    };
+    'prompt: loop {
+        // Apply changes to the config, asked for with `reload` or noticed in its files
+        let requested = builtin::take_reload();
+        if requested || (config.watch() && config.changed()) {
+            reload(&mut config, config_path.as_deref(), requested);
+            context.min_duration = config.duration_threshold();
         }
-        queue!(stdout(), Print("\n")).unwrap();
-        queue!(stdout(), Print("$ ")).unwrap();
+
+        title::idle(&context);
+        // The theme may have been switched by the last command
+        let scheme = config.get_scheme();
+
+        // The editor draws the prompt, so that it can draw it again after a resize.
+        // XCYS_PS1 takes precedence over the configured template.
+        let template = std::env::var("XCYS_PS1").unwrap_or_else(|_| config.prompt().to_string());
+        let right_template = std::env::var("XCYS_RPS1")
+            .ok()
+            .or_else(|| config.right_prompt().map(String::from))
+            .unwrap_or_default();
+        // Slow segments like git are filled in when they arrive
+        prompt::start(&[&template, &right_template], |name| {
+            config.segment_timeout(name)
+        });
+        let render_prompts = |context: &prompt::Context| {
+            let right = match right_template.is_empty() {
+                true => String::new(),
+                false => prompt::render(&right_template, context),
+            };
+            (prompt::render(&template, context), right)
+        };
+        let new_editor = |context: &prompt::Context| {
+            let (prompt, right_prompt) = render_prompts(context);
+            let mut editor = Editor::new(scheme, &prompt);
+            editor.right_prompt = right_prompt;
+            editor.transient = config.transient_prompt();
+            editor
+        };
 
         stdout().flush().unwrap();
-        let mut input = String::from("");
-        let screen_size = crossterm::terminal::size().unwrap();
-        let y = crossterm::cursor::position().unwrap().1;
+        let mut editor = new_editor(&context);
+        if let Some(buffer) = restore.take() {
+            editor.set_buffer(buffer);
+        }
+        editor.render();
+        // Keys of a sequence typed so far
+        let mut pending = vec![];
         'input: loop {
+            // Read before the update flag, so that a value arriving in between is drawn
+            let waiting = prompt::pending();
+            if prompt::take_updated() {
+                let (prompt, right_prompt) = render_prompts(&context);
+                editor.set_prompt(prompt);
+                editor.right_prompt = right_prompt;
+                editor.render();
+            }
+            // Check for arrived segments every now and then while waiting for a key
+            if waiting && !poll(Duration::from_millis(50)).unwrap() {
+                continue;
+            }
             let event = read().unwrap();
 
             match event {
@@ -81,138 +123,265 @@ fn shell_loop(scheme: ColorScheme) {
 
                 Event::FocusLost => {}
 
-                Event::Key(key) => match key {
-                    KeyEvent {
-                        code,
-                        modifiers,
-                        kind,
-                        ..
-                    } => {
-                        if kind == KeyEventKind::Release {
-                            match code {
-                                KeyCode::Backspace => {
-                                    if !input.is_empty() {
-                                        input.remove(input.len() - 1);
-
-                                        // Move
-                                        let pos = crossterm::cursor::position().unwrap();
-                                        if pos.0 == 0 && pos.1 != y {
-                                            execute!(
-                                                stdout(),
-                                                crossterm::cursor::MoveTo(screen_size.0, pos.1 - 1)
-                                            )
-                                            .unwrap();
-                                            continue;
-                                        } else if pos.0 == 2 && pos.1 == y {
-                                            continue;
-                                        }
-                                        execute!(stdout(), crossterm::cursor::MoveLeft(1)).unwrap();
-
-                                        execute!(
-                                            stdout(),
-                                            crossterm::terminal::Clear(
-                                                crossterm::terminal::ClearType::FromCursorDown
-                                            )
-                                        )
-                                        .unwrap();
-                                    }
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind,
+                    ..
+                }) => {
+                    if kind != KeyEventKind::Release {
+                        continue;
+                    }
+
+                    // Keys typed with Ctrl or Alt keep their emacs meaning in vi mode
+                    if vi::enabled()
+                        && !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                        && vi::handle(&mut editor, code)
+                    {
+                        editor.render();
+                        continue;
+                    }
+
+                    // While the completion menu is open, navigation keys move the selection.
+                    // Any other key accepts the selection and is then handled as usual.
+                    if editor.menu_key(code) {
+                        editor.render();
+                        continue;
+                    }
+
+                    pending.push(Chord::new(code, modifiers));
+                    let binding = match keymap::lookup(&pending) {
+                        Lookup::Exact(b) => {
+                            pending.clear();
+                            b
+                        }
+                        // Wait for the rest of the sequence
+                        Lookup::Prefix => continue,
+                        Lookup::None => {
+                            // Characters typed on their own are inserted
+                            if let [Chord {
+                                code: KeyCode::Char(c),
+                                modifiers,
+                            }] = pending[..]
+                            {
+                                if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                                {
+                                    editor.type_char(c);
+                                    editor.render();
                                 }
-                                KeyCode::Enter => match kind {
-                                    KeyEventKind::Press => {}
-                                    KeyEventKind::Repeat => {}
-                                    KeyEventKind::Release => {
-                                        println!();
-                                        match parse_command(&input) {
-                                            Ok(c) => match c {
-                                                CommandType::Executable(e) => match e.run() {
-                                                    Ok(_) => {}
-                                                    Err(e) => execute!(
-                                                        stdout(),
-                                                        SetForegroundColor(Color::Red),
-                                                        Print("Error: "),
-                                                        ResetColor,
-                                                        Print(e)
-                                                    )
-                                                    .unwrap(),
-                                                },
-                                                CommandType::Builtin(b) => {
-                                                    b.run();
-                                                }
-                                            },
-                                            Err(_) => break 'input,
-                                        };
-                                        input.clear();
-                                        break 'input;
-                                    }
-                                },
-                                KeyCode::Left => {}
-                                KeyCode::Right => {}
-                                KeyCode::Up => {}
-                                KeyCode::Down => {}
-                                KeyCode::Home => {}
-                                KeyCode::End => {}
-                                KeyCode::PageUp => {}
-                                KeyCode::PageDown => {}
-                                KeyCode::Tab => {}
-                                KeyCode::BackTab => {}
-                                KeyCode::Delete => {}
-                                KeyCode::Insert => {
-                                    break;
+                            }
+                            pending.clear();
+                            continue;
+                        }
+                    };
+
+                    match binding {
+                        Binding::Action(Action::AcceptLine) => {
+                            // Continue on the next line until the command is complete
+                            if is_incomplete(&editor.buffer) {
+                                editor.set_buffer(format!("{}\n", editor.buffer));
+                                editor.render();
+                                continue;
+                            }
+                            let input = editor.finish();
+                            println!();
+                            if config.paste_warning() && editor.pasted_lines {
+                                let lines = input.lines().count();
+                                if !confirm(&format!("Run {} pasted lines? [y/N] ", lines)) {
+                                    // Keep editing the same text on a fresh prompt
+                                    editor = new_editor(&context);
+                                    editor.set_buffer(input);
+                                    editor.render();
+                                    continue;
                                 }
-                                KeyCode::F(_) => {}
-                                KeyCode::Char(c) => {
-                                    {
-                                        // Case insensitivity
-                                        let c = c.to_ascii_lowercase();
-                                        if modifiers == KeyModifiers::CONTROL {
-                                            match c {
-                                                'c' => {
-                                                    std::process::exit(0);
-                                                }
-
-                                                _ => {}
-                                            }
-                                        }
-                                    }
-
-                                    match kind {
-                                        KeyEventKind::Press => {}
-                                        KeyEventKind::Repeat => {}
-                                        KeyEventKind::Release => {
-                                            input.push(c);
-                                            highlight(&mut input, scheme);
-                                        }
-                                    }
-                                    flush();
+                            }
+                            if let Ok(cwd) = std::env::current_dir() {
+                                history::add(&input, &cwd);
+                            }
+                            run(&input, &mut context);
+                            break 'input;
+                        }
+                        Binding::Action(Action::ClearScreen) => {
+                            queue!(stdout(), Clear(ClearType::All), MoveTo(0, 0)).unwrap();
+                            restore = Some(editor.buffer.clone());
+                            continue 'prompt;
+                        }
+                        Binding::Action(Action::EditCommandLine) => {
+                            let input = editor.finish();
+                            println!();
+                            match edit_externally(&input) {
+                                Ok(edited) => restore = Some(edited),
+                                Err(e) => {
+                                    restore = Some(input);
+                                    print_error(e);
                                 }
-                                KeyCode::Null => {}
-                                KeyCode::Esc => {}
-                                KeyCode::CapsLock => {}
-                                KeyCode::ScrollLock => {}
-                                KeyCode::NumLock => {}
-                                KeyCode::PrintScreen => {}
-                                KeyCode::Menu => {}
-                                KeyCode::KeypadBegin => {}
-                                KeyCode::Pause => {}
-                                KeyCode::Media(_) => {}
-                                KeyCode::Modifier(_) => {}
                             }
+                            break 'input;
+                        }
+                        Binding::Action(Action::Exit) => {
+                            restore_terminal();
+                            std::process::exit(0)
+                        }
+                        Binding::Action(action) => editor.perform(action),
+                        Binding::Command(command) => {
+                            // Run the command below the line and edit the line again afterwards
+                            restore = Some(editor.finish());
+                            println!();
+                            run(&command, &mut context);
+                            break 'input;
                         }
                     }
-                },
+                    editor.render();
+                }
 
-                Event::Mouse(_) => {}
+                Event::Mouse(event) => {
+                    if editor.mouse(event) {
+                        editor.render();
+                    }
+                }
 
-                Event::Paste(_) => {}
+                Event::Paste(text) => {
+                    editor.paste(&text);
+                    editor.render();
+                }
 
-                Event::Resize(_, _) => {}
+                Event::Resize(_, _) => editor.resize(),
             }
         }
         println!();
     }
 }
 
-pub static mut GIT_ENABLED: bool = false;
+/// Make the settings of a loaded config take effect. Problems are printed ending with `newline`.
+fn apply(config: &Config, newline: &str) {
+    match config.theme().map(theme::load) {
+        Some(Ok(t)) => theme::set_active(Some(t)),
+        Some(Err(e)) => print_warning(format!("{}{}", e, newline)),
+        // A theme removed from the config goes back to the default colors
+        None => theme::set_active(None),
+    }
+    vi::set_enabled(config.vi_mode());
+    keymap::load_defaults();
+    for (keys, binding) in config.keybindings() {
+        keymap::bind(keys.clone(), binding.clone());
+    }
+    segment::clear_options();
+    for (name, options) in config.segments() {
+        segment::set_options(name, options.clone());
+    }
+    title::set_templates(config.title(), config.running_title());
+}
+
+/// Load the config again and apply it. A broken config is reported and the current one kept.
+/// Key bindings made with `bind` are replaced by the configured ones.
+fn reload(config: &mut Config, path: Option<&Path>, report: bool) {
+    let new = match Config::load(path) {
+        Ok(new) => new,
+        Err(e) => {
+            print_error(format!("{}\r\nKeeping the current config.\r\n", e));
+            return;
+        }
+    };
+    for warning in new.warnings() {
+        print_warning(format!("{}\r\n", warning));
+    }
+    if new.mouse() != config.mouse() {
+        set_mouse_capture(new.mouse());
+    }
+    apply(&new, "\r\n");
+    *config = new;
+    if report {
+        execute!(stdout(), Print("Config reloaded\r\n")).unwrap();
+    }
+}
+
+/// Parse and run a command line, and note how it went for the prompt.
+/// Each line of it is run as its own command, in order.
+fn run(input: &str, context: &mut prompt::Context) {
+    context.command = Some(input.to_string());
+    title::running(context);
+    let started = Instant::now();
+    let commands = match parse_command(input) {
+        Ok(commands) => commands,
+        Err(e) if matches!(e.kind(), ErrorKind::Null) => {
+            title::idle(context);
+            return;
+        }
+        Err(e) => {
+            print_error(format!("{}\r\n", e));
+            // Like the status other shells give a syntax error
+            context.status = Some(2);
+            context.duration = None;
+            title::idle(context);
+            return;
+        }
+    };
+    for command in commands {
+        let status = match command {
+            CommandType::Executable(e) => match without_mouse(|| e.run()) {
+                Ok(status) => exit_code(status),
+                Err(e) => {
+                    print_error(&e);
+                    // Like other shells: 127 when the command isn't found, 126 when it can't run
+                    if e.kind() == std::io::ErrorKind::NotFound {
+                        127
+                    } else {
+                        126
+                    }
+                }
+            },
+            CommandType::Builtin(b) => {
+                let status = b.run();
+                // fc hands back the command it edited, to be run like a typed one
+                if let Some(command) = builtin::take_queued() {
+                    execute!(stdout(), Print(&command), Print("\r\n")).unwrap();
+                    if let Ok(cwd) = std::env::current_dir() {
+                        history::add(&command, &cwd);
+                    }
+                    run(&command, context);
+                    continue;
+                }
+                status
+            }
+        };
+        context.status = Some(status);
+    }
+    // The command may have changed files git shows in the prompt
+    git::invalidate();
+    context.duration = Some(started.elapsed());
+    title::idle(context);
+}
+
+/// Exit code of a command, 128+N when it was killed by signal N
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(1)
+}
+
+fn print_error(e: impl std::fmt::Display) {
+    execute!(
+        stdout(),
+        SetForegroundColor(Color::Red),
+        Print("Error: "),
+        ResetColor,
+        Print(e)
+    )
+    .unwrap();
+}
+
+fn print_warning(e: impl std::fmt::Display) {
+    execute!(
+        stdout(),
+        SetForegroundColor(Color::Yellow),
+        Print("Warning: "),
+        ResetColor,
+        Print(e)
+    )
+    .unwrap();
+}
 
 fn main() -> Result<()> {
     execute!(stdout(), crossterm::terminal::SetTitle("XCYS Shell")).unwrap();
@@ -221,20 +390,23 @@ fn main() -> Result<()> {
     let tag = get_version();
     let version = &tag.items[0].name;
 
-    // Load config
-    let config = match Config::load() {
-        Ok(c) => c,
-        Err(_) => Config::default(),
-    };
-
-    // Find out if Git is available. If not available,
-    // do not display the branch name (this has the effect of eliminating wasteful processing!)
-    if find("git").is_ok() {
-        unsafe {
-            GIT_ENABLED = true;
+    // Load config. A broken config is reported and the defaults are used instead.
+    let config_path = config_arg();
+    let config = match Config::load(config_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(format!("{}\nUsing the default config.\n", e));
+            Config::default()
         }
+    };
+    for warning in config.warnings() {
+        print_warning(format!("{}\n", warning));
     }
 
+    apply(&config, "\n");
+    completion::load_specs();
+    history::load();
+
     // Run shell
     enable_raw_mode()?;
 
@@ -268,168 +440,102 @@ fn main() -> Result<()> {
         .unwrap();
     }
 
-    shell_loop(config.get_scheme());
+    // Receive pasted text as a whole instead of as keystrokes
+    let _ = execute!(stdout(), EnableBracketedPaste);
+    if config.mouse() {
+        set_mouse_capture(true);
+    }
 
-    disable_raw_mode()
+    shell_loop(config, config_path);
+    restore_terminal();
+    Ok(())
 }
 
-#[tokio::main]
-// Get latest release from GitHub
-async fn get_version() -> Page<Tag> {
-    let t = octocrab::instance()
-        .repos("Lattexshz", "Xcys")
-        .list_tags()
-        .send()
-        .await;
-    t.unwrap()
+/// Whether the shell captures the mouse, as the config asks
+static MOUSE_CAPTURE: AtomicBool = AtomicBool::new(false);
+
+fn set_mouse_capture(enabled: bool) {
+    MOUSE_CAPTURE.store(enabled, Ordering::SeqCst);
+    let _ = match enabled {
+        true => execute!(stdout(), EnableMouseCapture),
+        false => execute!(stdout(), DisableMouseCapture),
+    };
 }
 
-fn to_suitable_style(s: &str) -> String {
-    if s == dirs::home_dir().unwrap().to_str().unwrap() {
-        return "~".parse().unwrap();
+/// Leave the mouse to another program, like a command or $EDITOR, while `f` runs
+fn without_mouse<T>(f: impl FnOnce() -> T) -> T {
+    let captured = MOUSE_CAPTURE.load(Ordering::SeqCst);
+    if captured {
+        let _ = execute!(stdout(), DisableMouseCapture);
     }
-    let mut s = String::from(s).replace(':', "");
-    if s.starts_with('C') {
-        s.remove(0);
-        s.insert(0, 'c');
+    let result = f();
+    if captured {
+        let _ = execute!(stdout(), EnableMouseCapture);
     }
-    s.replace('\\', "/")
+    result
 }
 
-fn find(program: &str) -> std::result::Result<Vec<u8>, ()> {
-    use std::process::Command;
-    let mut output = match Command::new("where").args([program]).output() {
-        Ok(o) => o,
-        Err(_) => {
-            return Err(());
-        }
-    };
-
-    if output.stdout.is_empty() {
-        return Err(());
-    }
-
-    output.stdout.remove(output.stdout.len() - 1);
-    output.stdout.remove(output.stdout.len() - 1);
-
-    Ok(output.stdout)
+/// Put the terminal back the way the shell found it. Called before the shell exits.
+fn restore_terminal() {
+    // The config may have turned the mouse on since startup, so it is always turned off
+    let _ = execute!(stdout(), DisableMouseCapture);
+    let _ = execute!(stdout(), DisableBracketedPaste);
+    let _ = disable_raw_mode();
 }
 
-fn get_git_branch_name() -> std::result::Result<Vec<u8>, bool> {
-    use std::process::Command;
-    let git = Path::new(".git");
-    if git.is_dir() && !git.exists() {
-        return Err(false);
-    }
-    if unsafe { crate::GIT_ENABLED } {
-        let mut output = match Command::new("git")
-            .args(["rev-parse", "--abbrev-ref", "@"])
-            .output()
-        {
-            Ok(o) => o,
-            Err(_) => {
-                return Err(false);
-            }
-        };
-
-        if output.stdout.is_empty() {
-            return Err(false);
+/// Path given with `--config PATH`, made canonical so that it still names the same file after a cd
+fn config_arg() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            path = args.next().map(PathBuf::from);
+            break;
+        }
+        if let Some(p) = arg.strip_prefix("--config=") {
+            path = Some(PathBuf::from(p));
+            break;
         }
-
-        output.stdout.remove(output.stdout.len() - 1);
-
-        Ok(output.stdout)
-    } else {
-        Err(false)
     }
+    path.map(|p| p.canonicalize().unwrap_or(p))
 }
 
-fn highlight(input: &mut str, scheme: ColorScheme) {
-    let vec: Vec<char> = input.chars().collect();
-    let pos = crossterm::cursor::position().unwrap();
-    use crossterm::cursor::MoveTo;
-    queue!(stdout(), MoveTo(2, pos.1), Clear(ClearType::FromCursorDown)).unwrap();
-
-    // 0: Command
-    // 1: Sub Command
-    // 2: String
-    // 3: Flags
-    let mut status = 0;
-
-    let mut d_quotation_count = 0;
-
-    for i in vec {
-        match i {
-            '"' => {
-                status = 2;
-                d_quotation_count += 1
-            }
-
-            '-' => {
-                if status != 2 {
-                    status = 3;
-                }
-            }
-
-            ' ' => {
-                if status != 2 {
-                    status = 1;
-                }
-            }
-
-            _ => {}
-        }
+#[tokio::main]
+// Get latest release from GitHub
+async fn get_version() -> Page<Tag> {
+    let t = octocrab::instance()
+        .repos("Lattexshz", "Xcys")
+        .list_tags()
+        .send()
+        .await;
+    t.unwrap()
+}
 
-        match status {
-            1 => queue!(
-                stdout(),
-                SetForegroundColor(scheme.sub_command()),
-                Print(i),
-                ResetColor
-            )
-            .unwrap(),
-
-            2 => queue!(
-                stdout(),
-                SetForegroundColor(scheme.string()),
-                Print(i),
-                ResetColor
-            )
-            .unwrap(),
-            3 => queue!(
-                stdout(),
-                SetForegroundColor(scheme.flags()),
-                Print(i),
-                ResetColor
-            )
-            .unwrap(),
-            _ => queue!(
-                stdout(),
-                SetForegroundColor(scheme.command()),
-                Print(i),
-                ResetColor
-            )
-            .unwrap(),
-        };
-        if d_quotation_count == 2 && status == 2 {
-            status = 1;
-            d_quotation_count = 0;
+/// Ask a yes/no question, answered with a single key
+fn confirm(question: &str) -> bool {
+    execute!(stdout(), Print(question)).unwrap();
+    loop {
+        if let Event::Key(KeyEvent {
+            code,
+            kind: KeyEventKind::Release,
+            ..
+        }) = read().unwrap()
+        {
+            execute!(stdout(), Print("\r\n")).unwrap();
+            return matches!(code, KeyCode::Char('y') | KeyCode::Char('Y'));
         }
     }
 }
 
-fn flush() {
-    stdout().flush().unwrap();
-}
-
+#[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn parse_command() {
-        let command = crate::command::parse_command("cargo check").unwrap();
+        let mut commands = crate::command::parse_command("cargo check").unwrap();
 
-        match command {
+        match commands.remove(0) {
             CommandType::Executable(command) => {
                 println!(
                     "{} {:?} {:?}",
@@ -0,0 +1,466 @@
+use crate::color::{self, ColorDepth};
+use crate::git;
+use crate::segment;
+use crate::theme;
+use crossterm::style::{
+    Attribute, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::Command;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// user@host, the directory, the git branch and how the last command went when it failed
+/// or took a while, then `$` on the next line
+pub const DEFAULT_PROMPT: &str =
+    "{green}{user}@{host} {yellow}{cwd_short}[ {cyan}({git})][ {red}✘{status}][ {dim}{duration}]{reset}\n$ ";
+
+/// Placeholders too slow to compute before showing the prompt
+const SLOW: [&str; 2] = ["git", "git_branch"];
+
+/// Shown in place of a slow placeholder until its value arrives,
+/// when there is no earlier value for the same directory
+const PENDING: &str = "…";
+
+/// Slow placeholders of the current prompt with the directory it is for,
+/// and their values, `None` while still computing
+static SEGMENTS: Mutex<Vec<(String, PathBuf, Option<String>)>> = Mutex::new(vec![]);
+
+/// Slow placeholders being computed and their directories. A prompt needing one of them
+/// waits for that computation rather than starting another.
+static RUNNING: Mutex<Vec<(String, PathBuf)>> = Mutex::new(vec![]);
+
+/// Last value computed for each slow placeholder and the directory it was computed in,
+/// shown while a new one is computed
+static LAST: Mutex<Vec<(String, PathBuf, String)>> = Mutex::new(vec![]);
+
+/// Counts the prompts, so that the timeout of an earlier one is ignored
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Set when a value has arrived and the prompt should be drawn again
+static UPDATED: AtomicBool = AtomicBool::new(false);
+
+/// Runtime computing the slow placeholders, kept for the whole session
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// The runtime the slow placeholders are computed on, also used by other work
+/// that must not block the shell for long
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap()
+    })
+}
+
+/// What the prompt can show about the last command
+#[derive(Default)]
+pub struct Context {
+    /// Exit status of the last command
+    pub status: Option<i32>,
+    /// How long the last command ran
+    pub duration: Option<Duration>,
+    /// Shorter durations aren't shown
+    pub min_duration: Duration,
+    /// The command running, or the last one once it has finished
+    pub command: Option<String>,
+    /// Jobs running in the background. The shell doesn't start any yet.
+    pub jobs: usize,
+}
+
+/// Expand a prompt template.
+///
+/// `{name}` is replaced by the value of a placeholder (user, host, cwd, cwd_short,
+/// cwd_base, git, git_branch, status, time, time:FORMAT, jobs, duration, command, the context segments
+/// rust, python, node and kube, or context for all of them) or starts a style
+/// (a color name, fg:COLOR, bg:COLOR, bold, dim, italic, underline, reverse, reset).
+/// Text in `[...]` is left out when every placeholder inside it is empty.
+/// Slow placeholders started with `start` show their last value, or `…`, until the new one arrives.
+/// A backslash makes the next character literal.
+pub fn render(template: &str, context: &Context) -> String {
+    // Groups being built, with whether a placeholder in them had a value
+    let mut groups = vec![(String::new(), false)];
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    groups.last_mut().unwrap().0.push(c);
+                }
+            }
+            '[' => groups.push((String::new(), false)),
+            ']' if groups.len() > 1 => {
+                let (text, shown) = groups.pop().unwrap();
+                if shown {
+                    let parent = groups.last_mut().unwrap();
+                    parent.0.push_str(&text);
+                    parent.1 = true;
+                }
+            }
+            '{' => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let group = groups.last_mut().unwrap();
+                if let Some(style) = style(&name) {
+                    group.0.push_str(&style);
+                } else if let Some(value) = placeholder(&name, context) {
+                    group.1 |= !value.is_empty();
+                    group.0.push_str(&value);
+                } else {
+                    group.0.push_str(&format!("{{{}}}", name));
+                }
+            }
+            c => groups.last_mut().unwrap().0.push(c),
+        }
+    }
+
+    // Unclosed groups are kept as if they were closed
+    while groups.len() > 1 {
+        let (text, shown) = groups.pop().unwrap();
+        if shown {
+            groups.last_mut().unwrap().0.push_str(&text);
+        }
+    }
+    let mut prompt = groups.pop().unwrap().0;
+    prompt.push_str(&ansi(ResetColor));
+    prompt.push_str(&ansi(SetAttribute(Attribute::Reset)));
+    prompt
+}
+
+/// Start computing the slow placeholders used in `templates` for a new prompt.
+/// Each one shows nothing once `timeout(name)` has passed. Its computation still
+/// goes on, and the value is kept for the next prompt in the same directory.
+pub fn start(templates: &[&str], timeout: impl Fn(&str) -> Duration) {
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    UPDATED.store(false, Ordering::SeqCst);
+    let mut segments = SEGMENTS.lock().unwrap();
+    segments.clear();
+
+    let runtime = runtime();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    for name in SLOW {
+        let tag = format!("{{{}}}", name);
+        if !templates.iter().any(|t| t.contains(&tag)) {
+            continue;
+        }
+        segments.push((name.to_string(), cwd.clone(), None));
+
+        let mut running = RUNNING.lock().unwrap();
+        if !running.iter().any(|(n, dir)| n == name && *dir == cwd) {
+            running.push((name.to_string(), cwd.clone()));
+            let dir = cwd.clone();
+            runtime.spawn_blocking(move || {
+                let value = slow_placeholder(name, &dir);
+                finish(name, &dir, value);
+            });
+        }
+
+        let limit = timeout(name);
+        runtime.spawn(async move {
+            tokio::time::sleep(limit).await;
+            give_up(generation, name);
+        });
+    }
+}
+
+/// Keep a computed value, and show it if the current prompt is waiting for it
+fn finish(name: &str, cwd: &Path, value: String) {
+    let mut segments = SEGMENTS.lock().unwrap();
+    RUNNING
+        .lock()
+        .unwrap()
+        .retain(|(n, dir)| !(n == name && dir == cwd));
+    let mut last = LAST.lock().unwrap();
+    last.retain(|(n, _, _)| n != name);
+    last.push((name.to_string(), cwd.to_path_buf(), value.clone()));
+
+    let waiting = segments
+        .iter_mut()
+        .find(|(n, dir, v)| n == name && dir == cwd && v.is_none());
+    if let Some(segment) = waiting {
+        segment.2 = Some(value);
+        UPDATED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Show nothing for a placeholder of prompt `generation` that is still being computed
+fn give_up(generation: usize, name: &str) {
+    let mut segments = SEGMENTS.lock().unwrap();
+    if GENERATION.load(Ordering::SeqCst) != generation {
+        return;
+    }
+    if let Some(segment) = segments
+        .iter_mut()
+        .find(|(n, _, v)| n == name && v.is_none())
+    {
+        segment.2 = Some(String::new());
+        UPDATED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Whether some slow placeholder of the current prompt is still being computed
+pub fn pending() -> bool {
+    SEGMENTS.lock().unwrap().iter().any(|(_, _, v)| v.is_none())
+}
+
+/// Whether values arrived since the last call, so that the prompt needs drawing again
+pub fn take_updated() -> bool {
+    UPDATED.swap(false, Ordering::SeqCst)
+}
+
+/// Value of a slow placeholder: computed in the background when `start` was called for it,
+/// in place otherwise
+fn segment(name: &str, cwd: PathBuf) -> String {
+    let segments = SEGMENTS.lock().unwrap();
+    match segments.iter().find(|(n, _, _)| n == name) {
+        Some((_, _, Some(value))) => value.clone(),
+        Some((_, _, None)) => match LAST.lock().unwrap().iter().find(|(n, _, _)| n == name) {
+            Some((_, dir, value)) if *dir == cwd => value.clone(),
+            _ => PENDING.to_string(),
+        },
+        None => {
+            drop(segments);
+            slow_placeholder(name, &cwd)
+        }
+    }
+}
+
+fn slow_placeholder(name: &str, cwd: &Path) -> String {
+    match name {
+        "git" => git::status(cwd).map(|s| s.summary()).unwrap_or_default(),
+        "git_branch" => git::branch(cwd).unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+fn style_reset() -> String {
+    ansi(ResetColor) + &ansi(SetAttribute(Attribute::Reset))
+}
+
+fn ansi(command: impl Command) -> String {
+    let mut s = String::new();
+    let _ = command.write_ansi(&mut s);
+    s
+}
+
+/// Escape sequence of a style tag
+fn style(name: &str) -> Option<String> {
+    let attribute = match name {
+        "reset" => return Some(style_reset()),
+        "bold" => Attribute::Bold,
+        "dim" => Attribute::Dim,
+        "italic" => Attribute::Italic,
+        "underline" => Attribute::Underlined,
+        "reverse" => Attribute::Reverse,
+        _ => {
+            let (background, color) = match name.split_once(':') {
+                Some(("fg", color)) => (false, color),
+                Some(("bg", color)) => (true, color),
+                Some(_) => return None,
+                None => (false, name),
+            };
+            // The active theme can give color names other values
+            let color = match theme::prompt_color(color) {
+                Some(color) => color,
+                None => color::downgrade(color::parse_color(color)?, ColorDepth::detect()),
+            };
+            return Some(if background {
+                ansi(SetBackgroundColor(color))
+            } else {
+                ansi(SetForegroundColor(color))
+            });
+        }
+    };
+    Some(ansi(SetAttribute(attribute)))
+}
+
+/// Value of a placeholder, empty when there is nothing to show
+fn placeholder(name: &str, context: &Context) -> Option<String> {
+    let cwd = || std::env::current_dir().unwrap_or_default();
+    let value = match name {
+        "user" => whoami::username(),
+        "host" => whoami::devicename(),
+        "cwd" => cwd().to_string_lossy().to_string(),
+        "cwd_short" => shorten(&cwd()),
+        "cwd_base" => match cwd().file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => cwd().to_string_lossy().to_string(),
+        },
+        name if SLOW.contains(&name) => segment(name, cwd()),
+        "status" => match context.status {
+            Some(0) | None => String::new(),
+            Some(status) => format_status(status),
+        },
+        "time" => format_time("%H:%M:%S"),
+        "jobs" => match context.jobs {
+            0 => String::new(),
+            jobs => jobs.to_string(),
+        },
+        name if segment::NAMES.contains(&name) => styled_segment(name, &cwd()),
+        "context" => segment::NAMES
+            .iter()
+            .map(|name| styled_segment(name, &cwd()))
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
+        "command" => context.command.clone().unwrap_or_default(),
+        "duration" => match context.duration {
+            Some(duration) if duration >= context.min_duration => format_duration(duration),
+            _ => String::new(),
+        },
+        _ => match name.strip_prefix("time:") {
+            Some(format) => format_time(format),
+            None => return None,
+        },
+    };
+    Some(value)
+}
+
+/// A context segment with its symbol and style, empty when disabled or not found
+fn styled_segment(name: &str, cwd: &Path) -> String {
+    let options = segment::options(name);
+    if !options.enabled {
+        return String::new();
+    }
+    match segment::detect(name, cwd) {
+        Some(value) => {
+            let style: String = options.style.split_whitespace().filter_map(style).collect();
+            format!("{}{}{}{}", style, options.symbol, value, style_reset())
+        }
+        None => String::new(),
+    }
+}
+
+/// The current time in a strftime format. Invalid formats show nothing.
+fn format_time(format: &str) -> String {
+    use std::fmt::Write;
+    let mut time = String::new();
+    match write!(time, "{}", chrono::Local::now().format(format)) {
+        Ok(_) => time,
+        Err(_) => String::new(),
+    }
+}
+
+/// The path with the home directory written as `~` and `/` between components
+fn shorten(path: &Path) -> String {
+    let path = match dirs::home_dir().and_then(|home| path.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => String::from("~"),
+        Some(rest) => format!("~/{}", rest.to_string_lossy()),
+        None => path.to_string_lossy().to_string(),
+    };
+    path.replace('\\', "/")
+}
+
+/// Names of the signals, by number
+const SIGNALS: [&str; 31] = [
+    "HUP", "INT", "QUIT", "ILL", "TRAP", "ABRT", "BUS", "FPE", "KILL", "USR1", "SEGV", "USR2",
+    "PIPE", "ALRM", "TERM", "STKFLT", "CHLD", "CONT", "STOP", "TSTP", "TTIN", "TTOU", "URG",
+    "XCPU", "XFSZ", "VTALRM", "PROF", "WINCH", "IO", "PWR", "SYS",
+];
+
+/// An exit status, with the signal name when it is 128+N for signal N, like "130 SIGINT"
+pub fn format_status(status: i32) -> String {
+    match status
+        .checked_sub(129)
+        .and_then(|n| SIGNALS.get(usize::try_from(n).ok()?))
+    {
+        Some(signal) => format!("{} SIG{}", status, signal),
+        None => status.to_string(),
+    }
+}
+
+/// Like 850ms, 4.2s or 1h3m
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    let secs = duration.as_secs();
+    if millis < 1000 {
+        format!("{}ms", millis)
+    } else if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m{}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::grapheme::strip_escapes;
+
+    #[test]
+    fn render_template() {
+        let context = Context {
+            status: Some(1),
+            duration: Some(Duration::from_millis(4200)),
+            min_duration: Duration::from_secs(5),
+            command: None,
+            jobs: 0,
+        };
+        let prompt = render(
+            "{red}[{status} ][{jobs} jobs ][{duration} ]\\[{nope}\\]$ ",
+            &context,
+        );
+        assert_eq!(strip_escapes(&prompt), "1 [{nope}]$ ");
+        let context = Context {
+            min_duration: Duration::from_secs(2),
+            ..context
+        };
+        assert_eq!(strip_escapes(&render("{duration}", &context)), "4.2s");
+        assert!(prompt.starts_with(&ansi(SetForegroundColor(crossterm::style::Color::Red))));
+    }
+
+    #[test]
+    fn pending_segments() {
+        let dir = std::env::temp_dir().join(format!("xcys-prompt-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = git2::Repository::init(&dir).unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        let other = dir.join("other");
+
+        // Computed in place when no prompt started it
+        SEGMENTS.lock().unwrap().clear();
+        let computed = segment("git_branch", dir.clone());
+
+        *SEGMENTS.lock().unwrap() = vec![(String::from("git"), dir.clone(), None)];
+        LAST.lock().unwrap().clear();
+        let waiting = segment("git", dir.clone());
+        *LAST.lock().unwrap() = vec![(String::from("git"), dir.clone(), String::from("main ?1"))];
+        let last = segment("git", dir.clone());
+        let elsewhere = segment("git", other);
+        SEGMENTS.lock().unwrap()[0].2 = Some(String::from("main"));
+        let arrived = segment("git", dir.clone());
+
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(computed, "main");
+        assert_eq!(waiting, PENDING);
+        assert_eq!(last, "main ?1");
+        assert_eq!(elsewhere, PENDING);
+        assert_eq!(arrived, "main");
+    }
+
+    #[test]
+    fn invalid_time_format() {
+        assert_eq!(format_time("%Q"), "");
+        assert_eq!(format_time("%H").len(), 2);
+    }
+
+    #[test]
+    fn statuses() {
+        assert_eq!(format_status(1), "1");
+        assert_eq!(format_status(130), "130 SIGINT");
+        assert_eq!(format_status(137), "137 SIGKILL");
+        assert_eq!(format_status(255), "255");
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(Duration::from_millis(850)), "850ms");
+        assert_eq!(format_duration(Duration::from_secs(63)), "1m3s");
+        assert_eq!(format_duration(Duration::from_secs(3780)), "1h3m");
+    }
+}
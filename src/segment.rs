@@ -0,0 +1,207 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Segments telling what project or environment the shell is in.
+/// They are found by reading files only, so nothing is run and they work offline.
+pub const NAMES: [&str; 4] = ["rust", "python", "node", "kube"];
+
+/// How a segment is shown
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub enabled: bool,
+    /// Style tags like "bold red", as in prompt templates
+    pub style: String,
+    /// Written before the value
+    pub symbol: String,
+}
+
+/// Options set in the config, replacing the defaults
+static OPTIONS: Mutex<Vec<(String, Options)>> = Mutex::new(vec![]);
+
+fn default_options(name: &str) -> Options {
+    let (style, symbol) = match name {
+        "rust" => ("red", "rs "),
+        "python" => ("yellow", "py "),
+        "node" => ("green", "node "),
+        _ => ("blue", "k8s "),
+    };
+    Options {
+        enabled: true,
+        style: style.to_string(),
+        symbol: symbol.to_string(),
+    }
+}
+
+pub fn set_options(name: &str, options: Options) {
+    let mut all = OPTIONS.lock().unwrap();
+    all.retain(|(n, _)| n != name);
+    all.push((name.to_string(), options));
+}
+
+/// Go back to the default options of every segment
+pub fn clear_options() {
+    OPTIONS.lock().unwrap().clear();
+}
+
+pub fn options(name: &str) -> Options {
+    match OPTIONS.lock().unwrap().iter().find(|(n, _)| n == name) {
+        Some((_, options)) => options.clone(),
+        None => default_options(name),
+    }
+}
+
+/// Text of a segment for the directory `cwd`, without symbol and style
+pub fn detect(name: &str, cwd: &Path) -> Option<String> {
+    match name {
+        "rust" => rust(cwd),
+        "python" => python(Path::new(&std::env::var_os("VIRTUAL_ENV")?)),
+        "node" => node(cwd),
+        "kube" => kube(&kube_config()?),
+        _ => None,
+    }
+}
+
+/// The nearest file called `name` in `dir` or a directory above it
+fn find_up(dir: &Path, name: &str) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(name))
+        .find(|path| path.is_file())
+}
+
+fn read_toml(path: &Path) -> Option<::toml::Table> {
+    ::toml::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+/// Like "stable v0.1.2": the toolchain pinned for the directory and the package version
+fn rust(cwd: &Path) -> Option<String> {
+    let toolchain = find_up(cwd, "rust-toolchain.toml")
+        .or_else(|| find_up(cwd, "rust-toolchain"))
+        .and_then(|path| {
+            let contents = std::fs::read_to_string(&path).ok()?;
+            // The old rust-toolchain file may hold just the channel
+            match ::toml::from_str::<::toml::Table>(&contents) {
+                Ok(table) => Some(
+                    table
+                        .get("toolchain")?
+                        .get("channel")?
+                        .as_str()?
+                        .to_string(),
+                ),
+                Err(_) => Some(contents.lines().next()?.trim().to_string()),
+            }
+        });
+    let version = find_up(cwd, "Cargo.toml")
+        .and_then(|path| read_toml(&path))
+        .and_then(|cargo| {
+            let version = cargo.get("package")?.get("version")?.as_str()?;
+            Some(format!("v{}", version))
+        });
+    join(toolchain, version)
+}
+
+/// Like "venv 3.11.4": the active virtual environment and its Python version
+fn python(venv: &Path) -> Option<String> {
+    let name = venv.file_name()?.to_string_lossy().to_string();
+    let version = std::fs::read_to_string(venv.join("pyvenv.cfg"))
+        .ok()
+        .and_then(|cfg| {
+            cfg.lines().find_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                match key.trim() {
+                    "version" | "version_info" => Some(value.trim().to_string()),
+                    _ => None,
+                }
+            })
+        });
+    join(Some(name), version)
+}
+
+/// Like "18 v1.0.0": the Node version from .nvmrc or package.json, and the package version
+fn node(cwd: &Path) -> Option<String> {
+    let package: Option<serde_json::Value> = find_up(cwd, "package.json")
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok());
+    let wanted = find_up(cwd, ".nvmrc")
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|contents| Some(contents.lines().next()?.trim().to_string()))
+        .or_else(|| Some(package.as_ref()?["engines"]["node"].as_str()?.to_string()));
+    let version = package
+        .as_ref()
+        .and_then(|p| Some(format!("v{}", p["version"].as_str()?)));
+    join(wanted, version)
+}
+
+/// The first file in $KUBECONFIG, or ~/.kube/config
+fn kube_config() -> Option<PathBuf> {
+    match std::env::var_os("KUBECONFIG") {
+        Some(paths) => std::env::split_paths(&paths).next(),
+        None => Some(dirs::home_dir()?.join(".kube").join("config")),
+    }
+}
+
+/// The current context named in a kubeconfig file
+fn kube(config: &Path) -> Option<String> {
+    let contents = std::fs::read_to_string(config).ok()?;
+    contents.lines().find_map(|line| {
+        let context = line.strip_prefix("current-context:")?.trim();
+        let context = context.trim_matches(|c| c == '"' || c == '\'');
+        (!context.is_empty()).then(|| context.to_string())
+    })
+}
+
+/// The parts found, separated by a space
+fn join(first: Option<String>, second: Option<String>) -> Option<String> {
+    let parts: Vec<String> = [first, second]
+        .into_iter()
+        .flatten()
+        .filter(|s| !s.is_empty())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_project_files() {
+        let dir = std::env::temp_dir().join(format!("xcys-segment-test-{}", std::process::id()));
+        let sub = dir.join("src");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(
+            dir.join("rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"1.70\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"a\"\nversion = \"0.3.0\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("package.json"),
+            r#"{"version": "1.0.0", "engines": {"node": ">=18"}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("pyvenv.cfg"),
+            "home = /usr/bin\nversion = 3.11.4\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("kubeconfig"),
+            "apiVersion: v1\ncurrent-context: \"prod\"\n",
+        )
+        .unwrap();
+
+        let rust = rust(&sub);
+        let node = node(&sub);
+        let python = python(&dir);
+        let kube = kube(&dir.join("kubeconfig"));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(rust.as_deref(), Some("1.70 v0.3.0"));
+        assert_eq!(node.as_deref(), Some(">=18 v1.0.0"));
+        assert!(python.unwrap().ends_with(" 3.11.4"));
+        assert_eq!(kube.as_deref(), Some("prod"));
+    }
+}
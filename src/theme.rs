@@ -0,0 +1,126 @@
+use crate::color::{self, ColorDepth, ColorScheme};
+use crossterm::style::Color;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const BUNDLED_THEMES: [(&str, &str); 4] = [
+    ("dark", include_str!("../res/themes/dark.toml")),
+    (
+        "high-contrast",
+        include_str!("../res/themes/high-contrast.toml"),
+    ),
+    ("light", include_str!("../res/themes/light.toml")),
+    ("solarized", include_str!("../res/themes/solarized.toml")),
+];
+
+/// Colors of the line editor and of the prompt
+#[derive(Clone)]
+pub struct Theme {
+    pub name: String,
+    pub scheme: ColorScheme,
+    /// Colors used in place of the named ones in prompt templates, like green = "#98c379"
+    pub prompt_colors: Vec<(String, Color)>,
+}
+
+/// Theme in use. Without one, the default colors are used.
+static ACTIVE: Mutex<Option<Theme>> = Mutex::new(None);
+
+pub fn themes_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("xcys").join("themes"))
+}
+
+/// Bundled themes and the ones in the themes directory, sorted by name
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = BUNDLED_THEMES
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    if let Some(Ok(entries)) = themes_dir().map(std::fs::read_dir) {
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension() == Some("toml".as_ref()) {
+                if let Some(name) = path.file_stem() {
+                    names.push(name.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Read a theme. A file in the themes directory takes the place of a bundled theme of the same name.
+pub fn load(name: &str) -> Result<Theme, String> {
+    let file = themes_dir().map(|dir| dir.join(format!("{}.toml", name)));
+    let contents = match file.and_then(|f| std::fs::read_to_string(f).ok()) {
+        Some(contents) => contents,
+        None => match BUNDLED_THEMES.iter().find(|(n, _)| *n == name) {
+            Some((_, contents)) => contents.to_string(),
+            None => return Err(format!("unknown theme {}", name)),
+        },
+    };
+    parse(name, &contents)
+}
+
+/// A theme from the contents of its file
+fn parse(name: &str, contents: &str) -> Result<Theme, String> {
+    let (scheme, prompt_colors) =
+        crate::toml::parse_theme(contents).map_err(|e| format!("theme {}: {}", name, e))?;
+
+    // Colors the terminal can't show are replaced by close ones
+    let depth = ColorDepth::detect();
+    Ok(Theme {
+        name: name.to_string(),
+        scheme: scheme.downgrade(depth),
+        prompt_colors: prompt_colors
+            .into_iter()
+            .map(|(name, c)| (name, color::downgrade(c, depth)))
+            .collect(),
+    })
+}
+
+pub fn set_active(theme: Option<Theme>) {
+    *ACTIVE.lock().unwrap() = theme;
+}
+
+pub fn active_name() -> Option<String> {
+    ACTIVE.lock().unwrap().as_ref().map(|t| t.name.clone())
+}
+
+/// Colors of the active theme
+pub fn scheme() -> Option<ColorScheme> {
+    ACTIVE.lock().unwrap().as_ref().map(|t| t.scheme)
+}
+
+/// What a color name in a prompt template stands for in the active theme
+pub fn prompt_color(name: &str) -> Option<Color> {
+    let active = ACTIVE.lock().unwrap();
+    let colors = &active.as_ref()?.prompt_colors;
+    colors
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, c)| *c)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bundled_themes_load() {
+        // Parsed directly, as load would prefer a file of the same name in the user's themes directory
+        for (name, contents) in BUNDLED_THEMES {
+            let theme = parse(name, contents).unwrap();
+            assert_eq!(theme.name, name);
+        }
+        assert!(
+            parse("dark", BUNDLED_THEMES[0].1)
+                .unwrap()
+                .prompt_colors
+                .len()
+                > 1
+        );
+        assert!(load("no-such-theme").is_err());
+        assert!(names().contains(&String::from("solarized")));
+    }
+}
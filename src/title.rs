@@ -0,0 +1,62 @@
+use crate::grapheme::strip_escapes;
+use crate::prompt::{self, Context};
+use crossterm::execute;
+use crossterm::terminal::SetTitle;
+use std::io::stdout;
+use std::sync::Mutex;
+
+/// The directory, like the prompt shows it
+pub const DEFAULT_IDLE: &str = "{cwd_short}";
+/// The command and where it runs
+pub const DEFAULT_RUNNING: &str = "{command} - {cwd_short}";
+
+/// Templates of the title while waiting for input and while a command runs
+static TEMPLATES: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+pub fn set_templates(idle: &str, running: &str) {
+    *TEMPLATES.lock().unwrap() = Some((idle.to_string(), running.to_string()));
+}
+
+/// Set the title shown while waiting for input
+pub fn idle(context: &Context) {
+    let template = match TEMPLATES.lock().unwrap().as_ref() {
+        Some((idle, _)) => idle.clone(),
+        None => DEFAULT_IDLE.to_string(),
+    };
+    set(&template, context);
+}
+
+/// Set the title shown while `context.command` runs
+pub fn running(context: &Context) {
+    let template = match TEMPLATES.lock().unwrap().as_ref() {
+        Some((_, running)) => running.clone(),
+        None => DEFAULT_RUNNING.to_string(),
+    };
+    set(&template, context);
+}
+
+fn set(template: &str, context: &Context) {
+    let _ = execute!(stdout(), SetTitle(render(template, context)));
+}
+
+/// A template expanded like a prompt, on one line and without styles
+fn render(template: &str, context: &Context) -> String {
+    strip_escapes(&prompt::render(template, context)).replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_title() {
+        let context = Context {
+            command: Some(String::from("cargo build\n--release")),
+            ..Default::default()
+        };
+        assert_eq!(
+            render("{bold}{command}[ ({status})]", &context),
+            "cargo build --release"
+        );
+    }
+}
@@ -1,94 +1,851 @@
-use crate::color::ColorScheme;
+use crate::color::{self, ColorDepth, ColorScheme, Style};
+use crate::error::ConfigError;
+use crate::keymap::{self, Action, Binding, Chord};
+use crate::prompt::DEFAULT_PROMPT;
+use crate::segment;
+use crate::theme;
+use crate::title;
 use crossterm::style::Color;
 use serde::Deserialize;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Deserialize)]
 pub struct UD {
-    pub ColorScheme: Option<UDColorScheme>,
+    /// Other config files read before this one
+    pub include: Option<UDInclude>,
+    /// Name of the theme giving the colors, which [ColorScheme] can change further
+    pub theme: Option<String>,
+    /// Reload the config at the next prompt when one of its files has changed
+    pub watch: Option<bool>,
+    #[serde(rename = "ColorScheme")]
+    pub color_scheme: Option<UDColorScheme>,
+    #[serde(rename = "Editor")]
+    pub editor: Option<UDEditor>,
+    #[serde(rename = "Prompt")]
+    pub prompt: Option<UDPrompt>,
+    #[serde(rename = "Title")]
+    pub title: Option<UDTitle>,
+    pub keybindings: Option<BTreeMap<String, UDBinding>>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum UDInclude {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// A theme file: a color scheme and the colors standing for color names in prompts
+#[derive(Debug, Deserialize)]
+pub struct UDTheme {
+    #[serde(rename = "ColorScheme")]
+    color_scheme: Option<UDColorScheme>,
+    #[serde(rename = "PromptColors")]
+    prompt_colors: Option<BTreeMap<String, String>>,
+}
+
+/// Style of each kind of token, like `command = "bold #ff8700"`
 #[derive(Debug, Deserialize)]
 pub struct UDColorScheme {
     command: Option<String>,
     sub_command: Option<String>,
     string: Option<String>,
     flags: Option<String>,
+    suggestion: Option<String>,
+    error: Option<String>,
+    operator: Option<String>,
+    redirection: Option<String>,
+    variable: Option<String>,
+}
+
+impl UDColorScheme {
+    /// Colors that are set, by key
+    fn colors(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("command", &self.command),
+            ("sub_command", &self.sub_command),
+            ("string", &self.string),
+            ("flags", &self.flags),
+            ("suggestion", &self.suggestion),
+            ("error", &self.error),
+            ("operator", &self.operator),
+            ("redirection", &self.redirection),
+            ("variable", &self.variable),
+        ]
+        .into_iter()
+        .filter_map(|(key, color)| Some((key, color.as_deref()?)))
+        .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UDEditor {
+    /// Ask before running a pasted command of several lines
+    paste_warning: Option<bool>,
+    /// "emacs" or "vi"
+    mode: Option<String>,
+    /// Click to move the cursor and scroll through the history.
+    /// Off by default, since it takes over the terminal's own selection.
+    mouse: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UDPrompt {
+    /// Template expanded by the prompt module, like "{cwd_short} $ "
+    format: Option<String>,
+    /// Template shown at the right end of the input line, like "{duration} {time}"
+    right: Option<String>,
+    /// Collapse the prompt of submitted lines to "$ "
+    transient: Option<bool>,
+    /// Milliseconds a slow segment like git may take before it is left empty
+    timeout: Option<u64>,
+    /// Timeouts of single segments, like `git = 200`
+    timeouts: Option<BTreeMap<String, u64>>,
+    /// Milliseconds a command has to run for `{duration}` to show
+    duration_threshold: Option<u64>,
+    /// Context segments like rust or kube, by name
+    segments: Option<BTreeMap<String, UDSegment>>,
+}
+
+/// `[Prompt.segments.rust]` with `enabled = false`, `style = "bold red"` or `symbol = "🦀 "`
+#[derive(Debug, Deserialize)]
+pub struct UDSegment {
+    enabled: Option<bool>,
+    style: Option<String>,
+    symbol: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UDTitle {
+    /// Template of the terminal title while waiting for input, like "{user}@{host}: {cwd_short}"
+    idle: Option<String>,
+    /// Template of the title while a command runs, like "{command}"
+    running: Option<String>,
+}
+
+/// `"ctrl-x ctrl-e" = "edit-command-line"` binds an action,
+/// `"alt-l" = { command = "ls" }` runs a command
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum UDBinding {
+    Action(String),
+    Command { command: String },
+}
+
+/// Completion definition for an external command, read from
+/// `<config dir>/xcys/completions/*.toml`
+#[derive(Debug, Deserialize, Clone)]
+pub struct CompletionSpec {
+    pub command: String,
+    #[serde(default)]
+    pub flags: Vec<String>,
+    /// Command whose output lines are offered as candidates
+    pub dynamic: Option<String>,
+    #[serde(default)]
+    pub subcommands: Vec<SubcommandSpec>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SubcommandSpec {
+    pub name: String,
+    #[serde(default)]
+    pub flags: Vec<String>,
+    pub dynamic: Option<String>,
 }
 
 pub struct Config {
-    ColorScheme: ColorScheme,
+    theme: Option<String>,
+    /// Styles set in [ColorScheme], by slot
+    color_styles: Vec<(String, Style)>,
+    paste_warning: bool,
+    vi_mode: bool,
+    mouse: bool,
+    prompt: String,
+    right_prompt: Option<String>,
+    transient_prompt: bool,
+    segment_timeout: Duration,
+    segment_timeouts: BTreeMap<String, Duration>,
+    duration_threshold: Duration,
+    segments: Vec<(String, segment::Options)>,
+    title: String,
+    running_title: String,
+    keybindings: Vec<(Vec<Chord>, Binding)>,
+    warnings: Vec<ConfigError>,
+    watch: bool,
+    /// Files the config was read from, with when they were last modified
+    files: Vec<(PathBuf, Option<SystemTime>)>,
 }
 
-impl Config {
-    pub fn load() -> Result<Self, ()> {
-        let file_path = Path::new("./xcys.toml");
-        let contents = match std::fs::read_to_string(file_path) {
-            Ok(c) => c,
-            Err(_) => {
-                return Err(());
+/// How long a slow prompt segment may take unless configured otherwise
+const SEGMENT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Commands running shorter than this don't show their duration unless configured otherwise
+const DURATION_THRESHOLD: Duration = Duration::from_millis(2000);
+
+/// Where the config is looked for: $XCYS_CONFIG, $XDG_CONFIG_HOME/xcys/config.toml,
+/// then ~/.xcys.toml. The first file that exists is used.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("XCYS_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    [
+        xdg_config_path(),
+        dirs::home_dir().map(|home| home.join(".xcys.toml")),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|path| path.is_file())
+}
+
+fn xdg_config_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => dirs::home_dir()?.join(".config"),
+    };
+    Some(dir.join("xcys").join("config.toml"))
+}
+
+/// The config file loaded last
+static LOADED: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Set a top-level key, like `theme = "dark"`, in the config file loaded at startup.
+/// Without one, $XDG_CONFIG_HOME/xcys/config.toml is created.
+pub fn save_setting(key: &str, value: toml::Value) -> std::io::Result<PathBuf> {
+    let path = match LOADED.lock().unwrap().clone().or_else(xdg_config_path) {
+        Some(path) => path,
+        None => return Err(std::io::Error::other("no config directory")),
+    };
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, set_key(&contents, key, &value))?;
+    Ok(path)
+}
+
+/// `contents` with the top-level `key` set to `value`, keeping everything else as written
+fn set_key(contents: &str, key: &str, value: &toml::Value) -> String {
+    let line = format!("{} = {}", key, value);
+    let mut lines: Vec<&str> = contents.lines().collect();
+    // Top-level keys come before the first table
+    let tables = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..tables]
+        .iter()
+        .position(|l| l.split_once('=').is_some_and(|(k, _)| k.trim() == key));
+    match existing {
+        Some(i) => lines[i] = &line,
+        None => lines.insert(0, &line),
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Read a config file together with the files it includes.
+/// `include = "colors.toml"` or a list of paths, relative to the including file.
+/// Included files are read first, so the including file overrides what they set.
+fn read_config(
+    path: &Path,
+    seen: &mut Vec<PathBuf>,
+    warnings: &mut Vec<ConfigError>,
+) -> Result<toml::Table, ConfigError> {
+    let error = |message: String| ConfigError {
+        file: path.to_path_buf(),
+        position: None,
+        key: None,
+        message,
+    };
+    let path = path.canonicalize().map_err(|e| error(e.to_string()))?;
+    // A file including itself, directly or not, is read once
+    if seen.contains(&path) {
+        return Ok(toml::Table::new());
+    }
+    seen.push(path.clone());
+
+    let contents = std::fs::read_to_string(&path).map_err(|e| error(e.to_string()))?;
+    let decoded = parse(&path, &contents, warnings)?;
+    check(&decoded, &path, &contents, warnings);
+    let mut table: toml::Table =
+        toml::from_str(&contents).map_err(|e| error(e.message().to_string()))?;
+    table.remove("include");
+
+    let includes = match decoded.include {
+        Some(UDInclude::One(include)) => vec![include],
+        Some(UDInclude::Many(includes)) => includes,
+        None => vec![],
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut merged = toml::Table::new();
+    for include in includes {
+        let included = dir.join(expand_home(&include));
+        if !included.is_file() {
+            let message = format!("{} not found", included.display());
+            warnings.push(warning(&path, &contents, "include", message));
+            continue;
+        }
+        merge(&mut merged, read_config(&included, seen, warnings)?);
+    }
+    merge(&mut merged, table);
+    Ok(merged)
+}
+
+/// Deserialize one config file. Keys that mean nothing are warned about.
+fn parse(path: &Path, contents: &str, warnings: &mut Vec<ConfigError>) -> Result<UD, ConfigError> {
+    let mut unknown = vec![];
+    let mut note = |key: serde_ignored::Path| unknown.push(key.to_string());
+    let deserializer =
+        serde_ignored::Deserializer::new(toml::Deserializer::new(contents), &mut note);
+    let decoded = serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let key = e.path().to_string();
+        ConfigError {
+            file: path.to_path_buf(),
+            position: e.inner().span().map(|span| position(contents, span.start)),
+            key: (key != ".").then_some(key),
+            message: e.inner().message().to_string(),
+        }
+    })?;
+    for key in unknown {
+        // Optional values show up as "?" in the path
+        let key: Vec<&str> = key.split('.').filter(|k| *k != "?").collect();
+        warnings.push(warning(path, contents, &key.join("."), "unknown key"));
+    }
+    Ok(decoded)
+}
+
+/// Warn about values that can't be used, like unknown color names
+fn check(decoded: &UD, path: &Path, contents: &str, warnings: &mut Vec<ConfigError>) {
+    let mut warn = |key: String, message: String| {
+        warnings.push(warning(path, contents, &key, message));
+    };
+    if let Some(scheme) = &decoded.color_scheme {
+        for (key, color) in scheme.colors() {
+            if color::parse_style(color).is_none() {
+                let message = format!("invalid style `{}`, using the default", color);
+                warn(format!("ColorScheme.{}", key), message);
             }
-        };
-        let decoded: UD = toml::from_str(&contents).unwrap();
+        }
+    }
+    if let Some(name) = &decoded.theme {
+        if !theme::names().contains(name) {
+            warn(String::from("theme"), format!("unknown theme `{}`", name));
+        }
+    }
+    if let Some(mode) = decoded.editor.as_ref().and_then(|e| e.mode.as_deref()) {
+        if !mode.eq_ignore_ascii_case("vi") && !mode.eq_ignore_ascii_case("emacs") {
+            let message = format!("unknown mode `{}`, expected \"emacs\" or \"vi\"", mode);
+            warn(String::from("Editor.mode"), message);
+        }
+    }
+    for (keys, binding) in decoded.keybindings.iter().flatten() {
+        let key = format!("keybindings.{}", keys);
+        if let Err(e) = keymap::parse_keys(keys) {
+            warn(key.clone(), e.to_string());
+        }
+        if let UDBinding::Action(action) = binding {
+            if Action::from_name(action).is_none() {
+                warn(key, format!("unknown action `{}`", action));
+            }
+        }
+    }
+    let segments = decoded.prompt.as_ref().and_then(|p| p.segments.as_ref());
+    for name in segments.into_iter().flat_map(|s| s.keys()) {
+        if !segment::NAMES.contains(&name.as_str()) {
+            warn(
+                format!("Prompt.segments.{}", name),
+                String::from("unknown segment"),
+            );
+        }
+    }
+}
+
+fn warning(path: &Path, contents: &str, key: &str, message: impl Into<String>) -> ConfigError {
+    ConfigError {
+        file: path.to_path_buf(),
+        position: locate(contents, key),
+        key: Some(key.to_string()),
+        message: message.into(),
+    }
+}
+
+/// Line and column of a byte offset, counted from 1
+fn position(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
 
-        // Initialize ColorScheme
-        let scheme = match decoded.ColorScheme {
-            None => {
-                println!("Use default");
-                ColorScheme::default()
+/// Where `key`, like "Editor.mode", is set in a file, if written as `mode = ...` under
+/// `[Editor]`, or where a table of that name starts
+fn locate(contents: &str, key: &str) -> Option<(usize, usize)> {
+    let (table, name) = key.rsplit_once('.').unwrap_or(("", key));
+    let unquote = |s: &str| s.trim().trim_matches(|c| c == '"' || c == '\'').to_string();
+    let mut current = String::new();
+    for (i, line) in contents.lines().enumerate() {
+        let column = line.len() - line.trim_start().len() + 1;
+        let line = line.trim();
+        if line.starts_with('[') {
+            current = unquote(line.trim_matches(|c| c == '[' || c == ']'));
+            if current == key {
+                return Some((i + 1, column));
             }
-            Some(s) => {
-                let command = match s.command {
-                    None => Color::Yellow,
-                    Some(c) => get_color_from_name(&c),
-                };
-                let sub_command = match s.sub_command {
-                    None => Color::White,
-                    Some(c) => get_color_from_name(&c),
-                };
-                let string = match s.string {
-                    None => Color::Green,
-                    Some(c) => get_color_from_name(&c),
-                };
-                let flags = match s.flags {
-                    None => Color::DarkGrey,
-                    Some(c) => get_color_from_name(&c),
-                };
+        } else if let Some((k, _)) = line.split_once('=') {
+            if current == table && unquote(k) == name {
+                return Some((i + 1, column));
+            }
+        }
+    }
+    None
+}
 
-                ColorScheme::new(command, sub_command, string, flags)
+/// Copy `other` into `table`. Tables present in both are merged, other values replaced.
+fn merge(table: &mut toml::Table, other: toml::Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(value)) => {
+                merge(existing, value)
+            }
+            (_, value) => {
+                table.insert(key, value);
             }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// `~/path` as a path in the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+impl Config {
+    /// Load the config from `path`, or from the first file found by `config_path`.
+    /// Without a config file the defaults are used.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let path = match path {
+            Some(path) => expand_home(&path.to_string_lossy()),
+            None => match config_path() {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+        };
+        let mut warnings = vec![];
+        // Kept canonical, so that a relative path still names the same file after a cd
+        let canonical = path.canonicalize().or_else(|_| std::path::absolute(&path));
+        *LOADED.lock().unwrap() = Some(canonical.unwrap_or_else(|_| path.clone()));
+        let mut files = vec![];
+        let table = read_config(&path, &mut files, &mut warnings)?;
+        let mut decoded: UD =
+            toml::Value::Table(table)
+                .try_into()
+                .map_err(|e: toml::de::Error| ConfigError {
+                    file: path.clone(),
+                    position: None,
+                    key: None,
+                    message: e.message().to_string(),
+                })?;
+
+        // Styles that can't be parsed keep their default, as warned about in `check`
+        let depth = ColorDepth::detect();
+        let color_styles = decoded
+            .color_scheme
+            .iter()
+            .flat_map(|s| s.colors())
+            .filter_map(|(slot, style)| {
+                let style = color::parse_style(style)?;
+                Some((slot.to_string(), color::downgrade_style(style, depth)))
+            })
+            .collect();
+
+        let paste_warning = decoded
+            .editor
+            .as_ref()
+            .and_then(|e| e.paste_warning)
+            .unwrap_or(true);
+        let vi_mode = decoded
+            .editor
+            .as_ref()
+            .and_then(|e| e.mode.as_deref())
+            .is_some_and(|m| m.eq_ignore_ascii_case("vi"));
+        let mouse = decoded
+            .editor
+            .as_ref()
+            .and_then(|e| e.mouse)
+            .unwrap_or(false);
+
+        let (prompt, right_prompt, transient_prompt) = match &decoded.prompt {
+            Some(p) => (
+                p.format
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_PROMPT.to_string()),
+                p.right.clone(),
+                p.transient.unwrap_or(false),
+            ),
+            None => (DEFAULT_PROMPT.to_string(), None, false),
+        };
+        let segment_timeout = decoded
+            .prompt
+            .as_ref()
+            .and_then(|p| p.timeout)
+            .map_or(SEGMENT_TIMEOUT, Duration::from_millis);
+        let duration_threshold = decoded
+            .prompt
+            .as_ref()
+            .and_then(|p| p.duration_threshold)
+            .map_or(DURATION_THRESHOLD, Duration::from_millis);
+        let (title, running_title) = match decoded.title {
+            Some(t) => (
+                t.idle.unwrap_or_else(|| title::DEFAULT_IDLE.to_string()),
+                t.running
+                    .unwrap_or_else(|| title::DEFAULT_RUNNING.to_string()),
+            ),
+            None => (
+                title::DEFAULT_IDLE.to_string(),
+                title::DEFAULT_RUNNING.to_string(),
+            ),
         };
 
+        // Segments with unknown names are ignored
+        let segments = decoded
+            .prompt
+            .as_mut()
+            .and_then(|p| p.segments.take())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(name, _)| segment::NAMES.contains(&name.as_str()))
+            .map(|(name, s)| {
+                let default = segment::options(&name);
+                let options = segment::Options {
+                    enabled: s.enabled.unwrap_or(default.enabled),
+                    style: s.style.unwrap_or(default.style),
+                    symbol: s.symbol.unwrap_or(default.symbol),
+                };
+                (name, options)
+            })
+            .collect();
+        let segment_timeouts = decoded
+            .prompt
+            .and_then(|p| p.timeouts)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, millis)| (name, Duration::from_millis(millis)))
+            .collect();
+
+        // Bindings with unknown keys or actions are ignored
+        let keybindings = decoded
+            .keybindings
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(keys, binding)| {
+                let keys = keymap::parse_keys(&keys).ok()?;
+                let binding = match binding {
+                    UDBinding::Action(a) => Binding::Action(Action::from_name(&a)?),
+                    UDBinding::Command { command } => Binding::Command(command),
+                };
+                Some((keys, binding))
+            })
+            .collect();
+
+        // A theme file of the user's own is watched along with the config
+        let theme_file = decoded.theme.as_ref().and_then(|name| {
+            let file = theme::themes_dir()?.join(format!("{}.toml", name));
+            file.is_file().then_some(file)
+        });
+        let files = files
+            .into_iter()
+            .chain(theme_file)
+            .map(|file| {
+                let time = modified(&file);
+                (file, time)
+            })
+            .collect();
+
         Ok(Self {
-            ColorScheme: scheme,
+            theme: decoded.theme,
+            color_styles,
+            paste_warning,
+            vi_mode,
+            mouse,
+            prompt,
+            right_prompt,
+            transient_prompt,
+            segment_timeout,
+            segment_timeouts,
+            duration_threshold,
+            segments,
+            title,
+            running_title,
+            keybindings,
+            warnings,
+            watch: decoded.watch.unwrap_or(false),
+            files,
         })
     }
 
+    /// The colors of the active theme, with the ones set in [ColorScheme] on top
     pub fn get_scheme(&self) -> ColorScheme {
-        self.ColorScheme
+        let mut scheme = theme::scheme().unwrap_or_default();
+        for (slot, style) in &self.color_styles {
+            scheme.set(slot, *style);
+        }
+        scheme
+    }
+
+    pub fn theme(&self) -> Option<&str> {
+        self.theme.as_deref()
+    }
+
+    pub fn paste_warning(&self) -> bool {
+        self.paste_warning
+    }
+
+    pub fn vi_mode(&self) -> bool {
+        self.vi_mode
+    }
+
+    pub fn mouse(&self) -> bool {
+        self.mouse
+    }
+
+    pub fn prompt(&self) -> &str {
+        &self.prompt
+    }
+
+    pub fn right_prompt(&self) -> Option<&str> {
+        self.right_prompt.as_deref()
+    }
+
+    pub fn transient_prompt(&self) -> bool {
+        self.transient_prompt
+    }
+
+    /// How long the slow prompt segment `name` may take
+    pub fn segment_timeout(&self, name: &str) -> Duration {
+        self.segment_timeouts
+            .get(name)
+            .copied()
+            .unwrap_or(self.segment_timeout)
+    }
+
+    pub fn duration_threshold(&self) -> Duration {
+        self.duration_threshold
+    }
+
+    pub fn segments(&self) -> &[(String, segment::Options)] {
+        &self.segments
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn running_title(&self) -> &str {
+        &self.running_title
+    }
+
+    pub fn keybindings(&self) -> &[(Vec<Chord>, Binding)] {
+        &self.keybindings
+    }
+
+    pub fn watch(&self) -> bool {
+        self.watch
+    }
+
+    /// Whether a file of the config, or the user's theme file, was modified since the last call
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (file, time) in &mut self.files {
+            let now = modified(file);
+            changed |= now != *time;
+            *time = now;
+        }
+        changed
+    }
+
+    /// Problems found in the config files that didn't keep them from loading
+    pub fn warnings(&self) -> &[ConfigError] {
+        &self.warnings
     }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            ColorScheme: ColorScheme::default(),
+            theme: None,
+            color_styles: vec![],
+            paste_warning: true,
+            vi_mode: false,
+            mouse: false,
+            prompt: DEFAULT_PROMPT.to_string(),
+            right_prompt: None,
+            transient_prompt: false,
+            segment_timeout: SEGMENT_TIMEOUT,
+            segment_timeouts: BTreeMap::new(),
+            duration_threshold: DURATION_THRESHOLD,
+            segments: vec![],
+            title: title::DEFAULT_IDLE.to_string(),
+            running_title: title::DEFAULT_RUNNING.to_string(),
+            keybindings: vec![],
+            warnings: vec![],
+            watch: false,
+            files: vec![],
+        }
+    }
+}
+
+pub fn completions_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("xcys").join("completions"))
+}
+
+/// Load every completion spec from the completions directory.
+/// Files that cannot be read or parsed are skipped.
+pub fn load_completion_specs() -> Vec<CompletionSpec> {
+    let entries = match completions_dir().map(std::fs::read_dir) {
+        Some(Ok(e)) => e,
+        _ => return vec![],
+    };
+
+    let mut specs = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension() != Some("toml".as_ref()) {
+            continue;
         }
+        if let Ok(spec) = std::fs::read_to_string(path)
+            .map_err(|_| ())
+            .and_then(|c| parse_completion_spec(&c))
+        {
+            specs.push(spec);
+        }
+    }
+    specs
+}
+
+/// The color scheme and prompt colors of a theme file
+pub fn parse_theme(contents: &str) -> Result<(ColorScheme, Vec<(String, Color)>), String> {
+    let decoded: UDTheme = toml::from_str(contents).map_err(|e| e.message().to_string())?;
+    let mut scheme = ColorScheme::default();
+    for (slot, style) in decoded.color_scheme.iter().flat_map(|s| s.colors()) {
+        let parsed = color::parse_style(style).ok_or(format!("invalid style `{}`", style))?;
+        scheme.set(slot, parsed);
+    }
+    let mut prompt_colors = vec![];
+    for (name, c) in decoded.prompt_colors.unwrap_or_default() {
+        let parsed = color::parse_color(&c).ok_or(format!("invalid color `{}`", c))?;
+        prompt_colors.push((name, parsed));
     }
+    Ok((scheme, prompt_colors))
+}
+
+pub fn parse_completion_spec(contents: &str) -> Result<CompletionSpec, ()> {
+    toml::from_str(contents).map_err(|_| ())
 }
 
-fn get_color_from_name(color: &str) -> Color {
-    match color.to_ascii_lowercase().as_str() {
-        "white" => Color::White,
-        "black" => Color::Black,
-        "grey" => Color::Grey,
-        "red" => Color::Red,
-        "darkred" => Color::DarkRed,
-        "green" => Color::Green,
-        "darkgreen" => Color::DarkGreen,
-        "blue" => Color::Blue,
-        "darkblue" => Color::DarkBlue,
-        "darkgrey" => Color::DarkGrey,
-        _ => Color::White,
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config_includes() {
+        let dir = std::env::temp_dir().join(format!("xcys-config-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("config.toml"),
+            "include = [\"base.toml\"]\n[Editor]\nmode = \"vi\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("base.toml"),
+            "include = \"config.toml\"\n[Editor]\nmode = \"emacs\"\nmouse = true\n",
+        )
+        .unwrap();
+
+        let config = Config::load(Some(&dir.join("config.toml")));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = config.unwrap();
+        assert!(config.vi_mode());
+        assert!(config.mouse());
+    }
+
+    #[test]
+    fn notice_changed_files() {
+        let dir = std::env::temp_dir().join(format!("xcys-watch-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "watch = true\n").unwrap();
+
+        let mut config = Config::load(Some(&path)).unwrap();
+        let unchanged = config.changed();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(later).unwrap();
+        let changed = config.changed();
+        let changed_again = config.changed();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(config.watch());
+        assert!(!unchanged);
+        assert!(changed);
+        assert!(!changed_again);
+    }
+
+    #[test]
+    fn set_top_level_key() {
+        let value = toml::Value::String(String::from("dark"));
+        assert_eq!(set_key("", "theme", &value), "theme = \"dark\"\n");
+        assert_eq!(
+            set_key(
+                "# mine\ntheme = \"light\"\n[Editor]\ntheme = 1",
+                "theme",
+                &value
+            ),
+            "# mine\ntheme = \"dark\"\n[Editor]\ntheme = 1\n"
+        );
+        assert_eq!(
+            set_key("[Editor]\nmode = \"vi\"\n", "theme", &value),
+            "theme = \"dark\"\n[Editor]\nmode = \"vi\"\n"
+        );
+    }
+
+    #[test]
+    fn config_errors() {
+        let path = Path::new("config.toml");
+        let contents = "[Editor]\nmode = 5\n";
+        let error = parse(path, contents, &mut vec![]).unwrap_err();
+        assert_eq!(error.position, Some((2, 8)));
+        assert_eq!(error.key.as_deref(), Some("Editor.mode"));
+
+        let contents =
+            "[Editor]\nmouse = true\n  modee = \"vi\"\n[ColorScheme]\nerror = \"bold redd\"\n";
+        let mut warnings = vec![];
+        let decoded = parse(path, contents, &mut warnings).unwrap();
+        check(&decoded, path, contents, &mut warnings);
+        let found: Vec<_> = warnings
+            .iter()
+            .map(|w| (w.key.as_deref().unwrap(), w.position.unwrap()))
+            .collect();
+        assert_eq!(
+            found,
+            [("Editor.modee", (3, 3)), ("ColorScheme.error", (5, 1))]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "config.toml:3:3: Editor.modee: unknown key"
+        );
     }
 }
@@ -0,0 +1,464 @@
+use crate::editor::{next_word_start, previous_word_start, word_end, Editor};
+use crate::grapheme;
+use crate::history;
+use crossterm::event::KeyCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the line editor works like vi rather than emacs
+static VI_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn enabled() -> bool {
+    VI_MODE.load(Ordering::SeqCst)
+}
+
+pub fn set_enabled(enabled: bool) {
+    VI_MODE.store(enabled, Ordering::SeqCst);
+}
+
+/// Largest count a command can be given, so that a long run of digits can't
+/// make `x` or `p` loop or allocate without end
+const MAX_COUNT: usize = 9999;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Insert,
+    Normal,
+}
+
+pub struct Vi {
+    pub mode: Mode,
+    /// Count typed before a command, 0 if none
+    count: usize,
+    /// Operator (d, c or y) waiting for its motion, with its count
+    operator: Option<(char, usize)>,
+    /// Text deleted or yanked last, put by p and P
+    register: String,
+    /// Keys of the command being typed
+    keys: Vec<KeyCode>,
+    /// Keys of the last change, repeated by `.`
+    last_change: Vec<KeyCode>,
+    /// The current insert belongs to a change and its keys are recorded
+    recording: bool,
+    replaying: bool,
+    /// Pattern being typed after `/`
+    pub search: Option<String>,
+    /// Last searched pattern and the history entry it matched
+    last_search: Option<(String, usize)>,
+}
+
+impl Default for Vi {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Insert,
+            count: 0,
+            operator: None,
+            register: String::new(),
+            keys: vec![],
+            last_change: vec![],
+            recording: false,
+            replaying: false,
+            search: None,
+            last_search: None,
+        }
+    }
+}
+
+/// Handle a key in vi mode.
+/// Returns false if the key should be handled like in emacs mode
+/// (text typed in insert mode, Enter to run the command).
+pub fn handle(editor: &mut Editor, code: KeyCode) -> bool {
+    let mut vi = std::mem::take(&mut editor.vi);
+    let handled = if vi.search.is_some() {
+        search_key(&mut vi, editor, code)
+    } else {
+        match vi.mode {
+            Mode::Insert => insert_key(&mut vi, editor, code),
+            Mode::Normal => normal_key(&mut vi, editor, code),
+        }
+    };
+    editor.vi = vi;
+    handled
+}
+
+fn insert_key(vi: &mut Vi, editor: &mut Editor, code: KeyCode) -> bool {
+    if code == KeyCode::Esc {
+        if vi.recording {
+            vi.keys.push(code);
+            finish_command(vi, true);
+            vi.recording = false;
+        }
+        vi.mode = Mode::Normal;
+        if editor.cursor > editor.line_start() {
+            editor.move_left();
+        }
+        return true;
+    }
+    if vi.recording && matches!(code, KeyCode::Char(_) | KeyCode::Backspace) {
+        vi.keys.push(code);
+    }
+    false
+}
+
+fn normal_key(vi: &mut Vi, editor: &mut Editor, code: KeyCode) -> bool {
+    let c = match code {
+        KeyCode::Char(c) => c,
+        KeyCode::Left | KeyCode::Backspace => 'h',
+        KeyCode::Right => 'l',
+        KeyCode::Up => 'k',
+        KeyCode::Down => 'j',
+        KeyCode::Home => '0',
+        KeyCode::End => '$',
+        KeyCode::Enter => return false,
+        KeyCode::Esc => {
+            vi.count = 0;
+            vi.operator = None;
+            vi.keys.clear();
+            return true;
+        }
+        _ => return true,
+    };
+    vi.keys.push(KeyCode::Char(c));
+
+    if c.is_ascii_digit() && (c != '0' || vi.count > 0) {
+        let digit = c.to_digit(10).unwrap() as usize;
+        vi.count = vi
+            .count
+            .saturating_mul(10)
+            .saturating_add(digit)
+            .min(MAX_COUNT);
+        return true;
+    }
+    let count = vi.count.max(1);
+    vi.count = 0;
+
+    if let Some((op, op_count)) = vi.operator.take() {
+        let count = count.saturating_mul(op_count).min(MAX_COUNT);
+        let range = if c == op {
+            Some(line_range(editor, op))
+        } else {
+            motion_range(editor, c, count, op)
+        };
+        if let Some((start, end)) = range {
+            apply_operator(vi, editor, op, start, end);
+        }
+        if vi.mode == Mode::Normal {
+            finish_command(vi, op != 'y' && range.is_some());
+        }
+        return true;
+    }
+
+    match c {
+        'd' | 'c' | 'y' => {
+            vi.operator = Some((c, count));
+            return true;
+        }
+        'i' => enter_insert(vi, editor),
+        'a' => {
+            if editor.cursor < editor.line_end() {
+                editor.move_right();
+            }
+            enter_insert(vi, editor);
+        }
+        'I' => {
+            editor.cursor = first_non_blank(editor);
+            enter_insert(vi, editor);
+        }
+        'A' => {
+            editor.cursor = editor.line_end();
+            enter_insert(vi, editor);
+        }
+        'x' | 's' => {
+            let end = forward_chars(editor, count).min(editor.line_end());
+            apply_operator(
+                vi,
+                editor,
+                if c == 'x' { 'd' } else { 'c' },
+                editor.cursor,
+                end,
+            );
+        }
+        'D' | 'C' => {
+            let end = editor.line_end();
+            apply_operator(
+                vi,
+                editor,
+                if c == 'D' { 'd' } else { 'c' },
+                editor.cursor,
+                end,
+            );
+        }
+        'p' | 'P' => {
+            if !vi.register.is_empty() {
+                editor.save_undo();
+                if c == 'p' && editor.cursor < editor.line_end() {
+                    editor.move_right();
+                }
+                for _ in 0..count {
+                    let register = vi.register.clone();
+                    editor.buffer.insert_str(editor.cursor, &register);
+                    editor.cursor += register.len();
+                }
+                editor.move_left();
+            }
+        }
+        'u' => editor.undo(),
+        '.' => {
+            vi.keys.clear();
+            repeat(vi, editor);
+            return true;
+        }
+        '/' => {
+            vi.keys.clear();
+            vi.search = Some(String::new());
+            return true;
+        }
+        'n' => search(vi, editor, true),
+        'N' => search(vi, editor, false),
+        'k' => {
+            for _ in 0..count {
+                editor.history_previous();
+            }
+            editor.cursor = 0;
+        }
+        'j' => {
+            for _ in 0..count {
+                editor.history_next();
+            }
+            editor.cursor = 0;
+        }
+        _ => {
+            if let Some(target) = motion(editor, c, count) {
+                editor.cursor = target;
+            }
+        }
+    }
+
+    if vi.mode == Mode::Normal {
+        let change = matches!(c, 'x' | 'D' | 'p' | 'P');
+        finish_command(vi, change);
+        clamp(editor);
+    }
+    true
+}
+
+/// A command has been typed completely. Remember it for `.` if it changed the line.
+fn finish_command(vi: &mut Vi, change: bool) {
+    let keys = std::mem::take(&mut vi.keys);
+    if change && !vi.replaying {
+        vi.last_change = keys;
+    }
+}
+
+fn enter_insert(vi: &mut Vi, editor: &mut Editor) {
+    editor.save_undo();
+    vi.mode = Mode::Insert;
+    vi.recording = true;
+}
+
+fn repeat(vi: &mut Vi, editor: &mut Editor) {
+    vi.replaying = true;
+    for code in vi.last_change.clone() {
+        let handled = match vi.mode {
+            Mode::Insert => insert_key(vi, editor, code),
+            Mode::Normal => normal_key(vi, editor, code),
+        };
+        if !handled {
+            match code {
+                KeyCode::Char(c) => editor.insert(c),
+                KeyCode::Backspace => editor.backspace(),
+                _ => {}
+            }
+        }
+    }
+    vi.replaying = false;
+}
+
+/// In normal mode the cursor is on a character, not after the last one
+fn clamp(editor: &mut Editor) {
+    if editor.cursor == editor.line_end() && editor.cursor > editor.line_start() {
+        editor.move_left();
+    }
+}
+
+fn forward_chars(editor: &Editor, count: usize) -> usize {
+    (0..count).fold(editor.cursor, |pos, _| grapheme::next(&editor.buffer, pos))
+}
+
+fn first_non_blank(editor: &Editor) -> usize {
+    let start = editor.line_start();
+    let line = &editor.buffer[start..editor.line_end()];
+    start + line.len() - line.trim_start().len()
+}
+
+/// Where motion `c` repeated `count` times moves the cursor
+fn motion(editor: &Editor, c: char, count: usize) -> Option<usize> {
+    let s = &editor.buffer;
+    let mut pos = editor.cursor;
+    match c {
+        'h' => {
+            let start = editor.line_start();
+            for _ in 0..count {
+                if pos > start {
+                    pos = grapheme::previous(s, pos);
+                }
+            }
+        }
+        'l' => pos = forward_chars(editor, count).min(editor.line_end()),
+        'w' => {
+            for _ in 0..count {
+                pos = next_word_start(s, pos);
+            }
+        }
+        'b' => {
+            for _ in 0..count {
+                pos = previous_word_start(s, pos);
+            }
+        }
+        'e' => {
+            for _ in 0..count {
+                pos = word_end(s, pos);
+            }
+        }
+        '0' => pos = editor.line_start(),
+        '^' => pos = first_non_blank(editor),
+        '$' => pos = editor.line_end(),
+        _ => return None,
+    }
+    Some(pos)
+}
+
+/// Range of text covered by operator `op` with motion `c`
+fn motion_range(editor: &Editor, c: char, count: usize, op: char) -> Option<(usize, usize)> {
+    // Like in vi, "cw" changes to the end of the word
+    let c = if op == 'c' && c == 'w' { 'e' } else { c };
+    let target = motion(editor, c, count)?;
+    let (start, mut end) = if target < editor.cursor {
+        (target, editor.cursor)
+    } else {
+        (editor.cursor, target)
+    };
+    // "e" includes the character it lands on
+    if c == 'e' {
+        end = grapheme::next(&editor.buffer, end);
+    }
+    Some((start, end))
+}
+
+/// Range of "dd", "cc" and "yy": the current line
+fn line_range(editor: &Editor, op: char) -> (usize, usize) {
+    let (start, end) = (editor.line_start(), editor.line_end());
+    if op != 'd' {
+        (start, end)
+    } else if end < editor.buffer.len() {
+        (start, end + 1)
+    } else {
+        (start.saturating_sub(1), end)
+    }
+}
+
+fn apply_operator(vi: &mut Vi, editor: &mut Editor, op: char, start: usize, end: usize) {
+    if start == end && op != 'c' {
+        return;
+    }
+    vi.register = editor.buffer[start..end].to_string();
+    editor.cursor = start;
+    if op == 'y' {
+        return;
+    }
+    editor.save_undo();
+    editor.buffer.replace_range(start..end, "");
+    if op == 'c' {
+        vi.mode = Mode::Insert;
+        vi.recording = true;
+    }
+}
+
+fn search_key(vi: &mut Vi, editor: &mut Editor, code: KeyCode) -> bool {
+    let pattern = vi.search.as_mut().unwrap();
+    match code {
+        KeyCode::Char(c) => pattern.push(c),
+        KeyCode::Backspace if pattern.is_empty() => vi.search = None,
+        KeyCode::Backspace => {
+            pattern.pop();
+        }
+        KeyCode::Enter => {
+            let pattern = vi.search.take().unwrap();
+            if !pattern.is_empty() {
+                vi.last_search = Some((pattern, history::len()));
+            }
+            search(vi, editor, true);
+        }
+        KeyCode::Esc => vi.search = None,
+        _ => {}
+    }
+    true
+}
+
+/// Load the next older (or newer) history entry matching the last search
+fn search(vi: &mut Vi, editor: &mut Editor, older: bool) {
+    let (pattern, index) = match &vi.last_search {
+        Some(s) => s.clone(),
+        None => return,
+    };
+    let found = if older {
+        history::search_backward(&pattern, index)
+    } else {
+        history::search_forward(&pattern, index)
+    };
+    if let Some((index, command)) = found {
+        editor.save_undo();
+        editor.cursor = command.find(&pattern).unwrap_or(0);
+        editor.buffer = command;
+        vi.last_search = Some((pattern, index));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::ColorScheme;
+
+    fn keys(editor: &mut Editor, keys: &str) {
+        for c in keys.chars() {
+            let code = if c == '\x1b' {
+                KeyCode::Esc
+            } else {
+                KeyCode::Char(c)
+            };
+            if !handle(editor, code) {
+                editor.insert(c);
+            }
+        }
+    }
+
+    #[test]
+    fn operators_and_repeat() {
+        let mut editor = Editor::new(ColorScheme::default(), "$ ");
+        keys(&mut editor, "git commit --amend\x1b0");
+        assert_eq!(editor.cursor, 0);
+
+        keys(&mut editor, "dw");
+        assert_eq!(editor.buffer, "commit --amend");
+        keys(&mut editor, ".");
+        assert_eq!(editor.buffer, "--amend");
+        keys(&mut editor, "uu");
+        assert_eq!(editor.buffer, "git commit --amend");
+
+        keys(&mut editor, "wcwpush\x1b");
+        assert_eq!(editor.buffer, "git push --amend");
+        keys(&mut editor, "$2x");
+        assert_eq!(editor.buffer, "git push --amen");
+        keys(&mut editor, "0yeP");
+        assert_eq!(editor.buffer, "gitgit push --amen");
+    }
+
+    #[test]
+    fn huge_counts() {
+        let mut editor = Editor::new(ColorScheme::default(), "$ ");
+        keys(&mut editor, "abc\x1b0");
+        keys(&mut editor, &"9".repeat(40));
+        keys(&mut editor, "yl");
+        keys(&mut editor, &format!("{}p", "9".repeat(40)));
+        assert_eq!(editor.buffer.len(), 3 + 3 * MAX_COUNT);
+    }
+}